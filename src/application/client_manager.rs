@@ -1,114 +1,318 @@
-use log::{error, info, warn};
-use serde_json::Value;
+use log::{debug, error, info, warn};
+use std::sync::Arc;
 use tokio::sync::broadcast;
+use tokio_rustls::rustls::ClientConfig;
 
-use crate::{domain::websocket_handler::WebSocketHandler, AppError};
-
-const COINGECKO_API_COINS: &str = "https://api.coingecko.com/api/v3/coins";
+use crate::{
+    config::TokenConfig,
+    domain::{
+        heartbeat::Heartbeat,
+        price_provider::{PriceProvider, TokenRef},
+        shutdown::Shutdown,
+        signing::ReportSigner,
+        websocket_handler::WebSocketHandler,
+        websocket_server::DEFAULT_SERVER_HOST,
+    },
+    infraestructure::{
+        address_cache::AddressCache, api_client::ApiClient, coingecko_client::CoinGeckoClient,
+    },
+    AppError,
+};
 
 #[derive(Debug)]
 pub struct Client {
-    token_name: String,
-    contract_address: String,
+    token: TokenRef,
+    provider: Arc<dyn PriceProvider>,
+    server_host: String,
+    heartbeat: Heartbeat,
+    shutdown: Shutdown,
+    signer: Option<Arc<ReportSigner>>,
+    tls: Option<Arc<ClientConfig>>,
     tx: broadcast::Sender<(String, String)>,
 }
 
 impl Client {
     /// Creates a new client instance.
     pub fn new(
-        token_name: String,
-        contract_address: String,
+        token: TokenRef,
+        provider: Arc<dyn PriceProvider>,
         tx: broadcast::Sender<(String, String)>,
     ) -> Self {
         Client {
-            token_name,
-            contract_address,
+            token,
+            provider,
+            server_host: DEFAULT_SERVER_HOST.to_string(),
+            heartbeat: Heartbeat::default(),
+            shutdown: Shutdown::new(),
+            signer: None,
+            tls: None,
             tx,
         }
     }
 
-    /// Starts the client's task by establishing a WebSocket connection.
-    pub async fn run(&self) {
-        let ws_handler = WebSocketHandler::new(
-            self.token_name.clone(),
-            self.contract_address.clone(),
-            self.tx.clone(),
-        );
-        if let Err(e) = ws_handler.connect().await {
-            error!("WebSocket connection error: {}", e);
+    /// Connects to the given server instead of [`DEFAULT_SERVER_HOST`].
+    pub fn with_server_host(mut self, host: &str) -> Self {
+        self.server_host = host.to_string();
+        self
+    }
+
+    /// Sets the heartbeat used to detect a dead server.
+    pub fn with_heartbeat(mut self, heartbeat: Heartbeat) -> Self {
+        self.heartbeat = heartbeat;
+        self
+    }
+
+    /// Stops the client once `shutdown` is triggered.
+    pub fn with_shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    /// Signs the client's price reports with the given key.
+    pub fn with_signer(mut self, signer: Arc<ReportSigner>) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    /// Connects to the server with `wss://` using the given TLS configuration.
+    pub fn with_tls(mut self, config: Arc<ClientConfig>) -> Self {
+        self.tls = Some(config);
+        self
+    }
+
+    /// Starts the client's task, keeping a WebSocket connection to the server open.
+    ///
+    /// Returns an error if the client stopped for good, such as when the server
+    /// refused its registration.
+    pub async fn run(&self) -> Result<(), AppError> {
+        let mut ws_handler =
+            WebSocketHandler::new(self.token.clone(), self.provider.clone(), self.tx.clone())
+                .with_server_host(&self.server_host)
+                .with_heartbeat(self.heartbeat)
+                .with_shutdown(self.shutdown.clone());
+        if let Some(signer) = &self.signer {
+            ws_handler = ws_handler.with_signer(signer.clone());
         }
+        if let Some(config) = &self.tls {
+            ws_handler = ws_handler.with_tls(config.clone());
+        }
+        ws_handler.run().await.inspect_err(|e| {
+            error!("WebSocket connection error: {}", e);
+        })
     }
 }
 
 // Manages all clients
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub struct ClientManager {
     clients: Vec<Client>,
+    provider: Arc<dyn PriceProvider>,
+    server_host: String,
+    heartbeat: Heartbeat,
+    shutdown: Shutdown,
+    signer: Option<Arc<ReportSigner>>,
+    tls: Option<Arc<ClientConfig>>,
+    coingecko: CoinGeckoClient,
+    address_cache: Option<AddressCache>,
+}
+
+impl Default for ClientManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ClientManager {
-    /// Creates a new ClientManager instance.
+    /// Creates a new ClientManager instance that fetches prices from DefiLlama.
     pub fn new() -> Self {
+        Self::with_provider(Arc::new(ApiClient::new()))
+    }
+
+    /// Creates a new ClientManager instance backed by the given price provider.
+    pub fn with_provider(provider: Arc<dyn PriceProvider>) -> Self {
         ClientManager {
             clients: Vec::new(),
+            provider,
+            server_host: DEFAULT_SERVER_HOST.to_string(),
+            heartbeat: Heartbeat::default(),
+            shutdown: Shutdown::new(),
+            signer: None,
+            tls: None,
+            coingecko: CoinGeckoClient::new(),
+            address_cache: None,
         }
     }
 
+    /// Connects every client to the given server instead of [`DEFAULT_SERVER_HOST`].
+    pub fn with_server_host(mut self, host: &str) -> Self {
+        self.server_host = host.to_string();
+        self
+    }
+
+    /// Sets the heartbeat used by the clients to detect a dead server.
+    pub fn with_heartbeat(mut self, heartbeat: Heartbeat) -> Self {
+        self.heartbeat = heartbeat;
+        self
+    }
+
+    /// Stops every client once `shutdown` is triggered.
+    pub fn with_shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    /// Signs the reports of every client with the given key.
+    pub fn with_signer(mut self, signer: Arc<ReportSigner>) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    /// Connects every client to the server with `wss://` using the given TLS configuration.
+    pub fn with_tls(mut self, config: Arc<ClientConfig>) -> Self {
+        self.tls = Some(config);
+        self
+    }
+
+    /// Looks up the contract addresses of the tokens with the given CoinGecko client.
+    pub fn with_coingecko(mut self, coingecko: CoinGeckoClient) -> Self {
+        self.coingecko = coingecko;
+        self
+    }
+
+    /// Reuses the contract addresses resolved by previous runs, and records the new ones.
+    pub fn with_address_cache(mut self, cache: AddressCache) -> Self {
+        self.address_cache = Some(cache);
+        self
+    }
+
     /// Creates clients based on the provided token list.
+    ///
+    /// Tokens declaring their coin type are used as is; the contract address of the
+    /// others is read from the address cache or looked up on CoinGecko, skipping the
+    /// tokens it does not list.
     pub async fn create_clients(
         &mut self,
-        tokens: Vec<String>,
+        tokens: Vec<TokenConfig>,
         tx: broadcast::Sender<(String, String)>,
     ) -> Result<(), AppError> {
         for token in tokens {
-            let url = format!("{}/{}", COINGECKO_API_COINS, token.to_lowercase());
-            let response: Value = reqwest::get(&url)
-                .await
-                .map_err(|e| AppError::ApiError(format!("Error calling {}: {}", url, e)))?
-                .json()
-                .await
-                .map_err(|e| AppError::ApiError(format!("Error parsing JSON response: {}", e)))?;
-
-            // Handle error if token is not found
-            if let Some(error_message) = response.get("error").and_then(|e| e.as_str()) {
-                if error_message == "coin not found" {
-                    warn!(
-                        "Token not found: {}. API response: {}",
-                        token, error_message
-                    );
-                    continue; // Skip to next token
-                }
+            let contract_address = match token.coin_type {
+                Some(coin_type) => coin_type,
+                None => match self.resolve_contract_address(&token.symbol).await? {
+                    Some(contract_address) => contract_address,
+                    None => continue, // Skip to next token
+                },
+            };
+
+            let mut client = Client::new(
+                TokenRef::new(token.symbol.clone(), contract_address),
+                self.provider.clone(),
+                tx.clone(),
+            )
+            .with_server_host(&self.server_host)
+            .with_heartbeat(self.heartbeat)
+            .with_shutdown(self.shutdown.clone());
+            if let Some(signer) = &self.signer {
+                client = client.with_signer(signer.clone());
             }
+            if let Some(config) = &self.tls {
+                client = client.with_tls(config.clone());
+            }
+            self.clients.push(client);
+            info!("Client created: {}", token.symbol);
+        }
+
+        if let Some(cache) = &self.address_cache {
+            if let Err(e) = cache.save() {
+                warn!("{}", e);
+            }
+        }
+        Ok(())
+    }
 
-            // If no error, create the client
+    /// Looks up the contract address of every token not declaring its coin type and
+    /// stores it in the cache, however recently it was resolved.
+    ///
+    /// Returns the number of addresses resolved.
+    pub async fn refresh_address_cache(
+        &self,
+        cache: &mut AddressCache,
+        tokens: &[TokenConfig],
+    ) -> Result<usize, AppError> {
+        let mut resolved = 0;
+        for token in tokens.iter().filter(|t| t.coin_type.is_none()) {
             if let Some(contract_address) =
-                response.pointer("/platforms/sui").and_then(|c| c.as_str())
+                self.coingecko.fetch_contract_address(&token.symbol).await?
             {
-                let client = Client::new(token.clone(), contract_address.to_string(), tx.clone());
-                self.clients.push(client);
-                info!("Client created: {}", token);
-            } else {
-                warn!("Contract address not found for token: {}", token);
+                cache.insert(&token.symbol, &contract_address);
+                resolved += 1;
             }
         }
-        Ok(())
+        cache.save()?;
+        Ok(resolved)
+    }
+
+    /// Returns the contract address of a token, preferring a fresh cached one to a lookup.
+    ///
+    /// When the lookup fails, an expired cached address is used rather than failing.
+    async fn resolve_contract_address(&mut self, token: &str) -> Result<Option<String>, AppError> {
+        let Some(cache) = self.address_cache.as_mut() else {
+            return self.coingecko.fetch_contract_address(token).await;
+        };
+        if let Some(contract_address) = cache.get(token) {
+            debug!("Using cached contract address for token: {}", token);
+            return Ok(Some(contract_address.to_string()));
+        }
+
+        match self.coingecko.fetch_contract_address(token).await {
+            Ok(Some(contract_address)) => {
+                cache.insert(token, &contract_address);
+                Ok(Some(contract_address))
+            }
+            Ok(None) => Ok(None),
+            Err(e) => match cache.get_stale(token) {
+                Some(cached) => {
+                    warn!(
+                        "Using contract address of {} resolved at {}: {}",
+                        token, cached.resolved_at, e
+                    );
+                    Ok(Some(cached.coin_type.clone()))
+                }
+                None => Err(e),
+            },
+        }
     }
 
-    /// Runs tasks for all clients asynchronously.
-    pub async fn run_clients(self) {
+    /// Runs tasks for all clients asynchronously, returning once all of them have
+    /// stopped, which happens on shutdown.
+    ///
+    /// # Returns
+    /// * `Err(AppError)` with the first client error if the clients stopped without
+    ///   a shutdown being requested.
+    pub async fn run_clients(self) -> Result<(), AppError> {
         let mut handlers = vec![];
 
         for client in self.clients {
-            handlers.push(tokio::spawn(async move {
-                client.run().await;
-            }));
+            handlers.push(tokio::spawn(async move { client.run().await }));
         }
 
         // Await all client tasks
+        let mut failure = None;
         for handler in handlers {
-            if let Err(e) = handler.await {
-                error!("Error in client task: {}", e);
+            let result = handler.await.unwrap_or_else(|e| {
+                Err(AppError::UnknownError(format!(
+                    "Error in client task: {}",
+                    e
+                )))
+            });
+            if let Err(e) = result {
+                failure.get_or_insert(e);
             }
         }
+
+        match failure {
+            Some(e) if !self.shutdown.is_triggered() => Err(e),
+            _ => Ok(()),
+        }
     }
 }
@@ -1 +1,2 @@
-pub mod client_manager;
+pub mod client_manager;
+pub mod price_aggregator;
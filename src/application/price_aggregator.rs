@@ -0,0 +1,193 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use futures_util::future::join_all;
+use log::{debug, warn};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::{
+    domain::{
+        price_provider::{PriceProvider, PriceQuote, TokenRef},
+        quote_policy::{QuoteFlag, QuotePolicy},
+    },
+    AppError,
+};
+
+/// Default band around the median, in basis points, outside which quotes are rejected.
+pub const DEFAULT_MAX_DEVIATION_BPS: u32 = 200;
+
+// Aggregates quotes from several providers into a single price.
+#[derive(Debug)]
+pub struct PriceAggregator {
+    providers: Vec<Arc<dyn PriceProvider>>,
+    max_deviation_bps: u32,
+    min_sources: usize,
+    default_policy: QuotePolicy,
+    policies: HashMap<String, QuotePolicy>,
+}
+
+impl PriceAggregator {
+    /// Creates a new PriceAggregator instance.
+    ///
+    /// # Arguments
+    /// * `providers` - The providers queried for every token.
+    /// * `max_deviation_bps` - Maximum distance from the median, in basis points, for a quote to be kept.
+    /// * `min_sources` - Minimum number of agreeing quotes required to report a price.
+    pub fn new(
+        providers: Vec<Arc<dyn PriceProvider>>,
+        max_deviation_bps: u32,
+        min_sources: usize,
+    ) -> Self {
+        PriceAggregator {
+            providers,
+            max_deviation_bps,
+            min_sources: min_sources.max(1),
+            default_policy: QuotePolicy::default(),
+            policies: HashMap::new(),
+        }
+    }
+
+    /// Sets the quote policy of the tokens without their own.
+    pub fn with_quote_policy(mut self, policy: QuotePolicy) -> Self {
+        self.default_policy = policy;
+        self
+    }
+
+    /// Sets the quote policy of a specific token.
+    pub fn with_token_quote_policy(mut self, token: &str, policy: QuotePolicy) -> Self {
+        self.policies.insert(token.to_uppercase(), policy);
+        self
+    }
+
+    /// Returns the quote policy of a token.
+    pub fn policy_for(&self, token: &str) -> QuotePolicy {
+        self.policies
+            .get(&token.to_uppercase())
+            .copied()
+            .unwrap_or(self.default_policy)
+    }
+
+    /// Aggregates a set of quotes for the same token.
+    ///
+    /// Quotes further than `max_deviation_bps` from the median are dropped; the
+    /// result is the median of the remaining quotes, stamped with the most recent
+    /// timestamp, the number of quotes that agreed, their lowest confidence and
+    /// their flags.
+    pub fn aggregate_quotes(
+        quotes: &[PriceQuote],
+        max_deviation_bps: u32,
+    ) -> Result<PriceQuote, AppError> {
+        let prices: Vec<f64> = quotes.iter().map(|q| q.price).collect();
+        let reference = median(&prices).ok_or(AppError::ApiResponseError(
+            "No quotes to aggregate".to_string(),
+        ))?;
+
+        let band = f64::from(max_deviation_bps) / 10_000.0;
+        let (accepted, rejected): (Vec<&PriceQuote>, Vec<&PriceQuote>) = quotes
+            .iter()
+            .partition(|q| reference == 0.0 || ((q.price - reference) / reference).abs() <= band);
+
+        for rejected in rejected {
+            warn!(
+                "Rejected outlier quote for {}: {} (median {})",
+                rejected.symbol, rejected.price, reference
+            );
+        }
+
+        let accepted_prices: Vec<f64> = accepted.iter().map(|q| q.price).collect();
+        let price = median(&accepted_prices).ok_or(AppError::ApiResponseError(
+            "All quotes rejected as outliers".to_string(),
+        ))?;
+        let latest =
+            accepted
+                .iter()
+                .max_by_key(|q| q.timestamp)
+                .ok_or(AppError::ApiResponseError(
+                    "All quotes rejected as outliers".to_string(),
+                ))?;
+
+        Ok(PriceQuote {
+            symbol: latest.symbol.clone(),
+            price,
+            timestamp: latest.timestamp,
+            sources: accepted.iter().map(|q| q.sources).sum(),
+            confidence: accepted
+                .iter()
+                .filter_map(|q| q.confidence)
+                .min_by(|a, b| a.total_cmp(b)),
+            flags: accepted.iter().flat_map(|q| q.flags.clone()).collect(),
+        })
+    }
+}
+
+#[async_trait]
+impl PriceProvider for PriceAggregator {
+    fn name(&self) -> &str {
+        "aggregator"
+    }
+
+    async fn fetch(&self, token: &TokenRef) -> Result<PriceQuote, AppError> {
+        let results = join_all(self.providers.iter().map(|p| p.fetch(token))).await;
+
+        let policy = self.policy_for(&token.name);
+        let now = Utc::now();
+        let mut quotes = Vec::new();
+        let mut flagged = Vec::new();
+        for (provider, result) in self.providers.iter().zip(results) {
+            match result {
+                Ok(mut quote) => {
+                    quote.flags = policy
+                        .check(&quote, now)
+                        .into_iter()
+                        .map(|flag| QuoteFlag {
+                            message: format!("{}: {}", provider.name(), flag.message),
+                            ..flag
+                        })
+                        .collect();
+                    if quote.flags.is_empty() {
+                        quotes.push(quote);
+                    } else {
+                        warn!("Excluded quote for {}: {:?}", token.name, quote.flags);
+                        flagged.push(quote);
+                    }
+                }
+                Err(e) => warn!(
+                    "Provider {} failed for {}: {}",
+                    provider.name(),
+                    token.name,
+                    e
+                ),
+            }
+        }
+
+        // Report a flagged price rather than none, the server leaves it unpublished
+        if quotes.is_empty() {
+            quotes = flagged;
+        }
+
+        let mut aggregated = Self::aggregate_quotes(&quotes, self.max_deviation_bps)?;
+        // Providers name tokens differently, report them under the configured name
+        aggregated.symbol = token.name.clone();
+        if aggregated.sources < self.min_sources {
+            return Err(AppError::ApiResponseError(format!(
+                "Only {} of {} required sources agreed for {}",
+                aggregated.sources, self.min_sources, token.name
+            )));
+        }
+
+        debug!("Aggregated quote: {:?}", aggregated);
+        Ok(aggregated)
+    }
+}
+
+/// Returns the median of the given values, or `None` if there are none.
+fn median(values: &[f64]) -> Option<f64> {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let mid = sorted.len() / 2;
+    match sorted.len() {
+        0 => None,
+        n if n % 2 == 0 => Some((sorted[mid - 1] + sorted[mid]) / 2.0),
+        _ => Some(sorted[mid]),
+    }
+}
@@ -1,28 +1,138 @@
+use clap::{Parser, Subcommand};
 use dotenv::dotenv;
+use log::{error, info};
+use std::process::ExitCode;
+use std::sync::Arc;
 use tokio::sync::broadcast;
 
-use suicrypto_oracle::{application::client_manager::ClientManager, config::Config, AppError};
+use suicrypto_oracle::{
+    application::{client_manager::ClientManager, price_aggregator::PriceAggregator},
+    config::{Config, ConfigArgs},
+    domain::{
+        price_provider::PriceProvider,
+        shutdown::{wait_for_signal, Shutdown},
+        signing::ReportSigner,
+    },
+    AppError,
+};
+
+/// Oracle client reporting token prices to the server.
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Cli {
+    #[command(flatten)]
+    config: ConfigArgs,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Looks up the contract address of every token on CoinGecko and updates the
+    /// address cache, then exits
+    RefreshCache,
+}
 
 // Main entry point of the program
+//
+// Exits with code 0 after a SIGINT/SIGTERM shutdown and 1 if the clients cannot start
+// or all stop on their own, such as when the server refuses their registration.
 #[tokio::main]
-async fn main() -> Result<(), AppError> {
+async fn main() -> ExitCode {
     dotenv().ok(); // Load environment variables
     env_logger::init(); // Initialize logging
+    let cli = Cli::parse();
+
+    match run(cli).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            error!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Creates a client per configured token and runs them until a shutdown signal is received.
+async fn run(cli: Cli) -> Result<(), AppError> {
+    // Load the configuration file, overridden by environment variables and flags
+    let config = Config::load(&cli.config)?;
+    let settings = &config.client;
 
-    // Load the configuration
-    let config = Config::load_from_file("tokens.json")?;
+    if let Some(Command::RefreshCache) = cli.command {
+        let mut cache = settings.address_cache();
+        let resolved = ClientManager::new()
+            .with_coingecko(settings.coingecko())
+            .refresh_address_cache(&mut cache, &config.tokens)
+            .await?;
+        info!(
+            "Cached {} contract addresses in {}",
+            resolved, settings.address_cache_path
+        );
+        return Ok(());
+    }
 
-    // Access token list from the configuration
-    let tokens = config.tokens;
+    // Build the price aggregator from the configured providers
+    let mut aggregator = build_aggregator(&config).with_quote_policy(config.default_quote_policy());
+    for (token, policy) in config.token_quote_policies() {
+        aggregator = aggregator.with_token_quote_policy(&token, policy);
+    }
 
     // Create a channel for broadcast messages
     let (tx, _) = broadcast::channel::<(String, String)>(100);
 
-    let mut client_manager = ClientManager::new();
-    client_manager.create_clients(tokens, tx).await?;
+    // Load the key signing the price reports, creating one on first run
+    let signer = ReportSigner::load_or_generate(&settings.key_path)?;
+    info!(
+        "Signing price reports with public key {}",
+        signer.public_key()
+    );
+
+    let shutdown = Shutdown::new();
+    let mut client_manager = ClientManager::with_provider(Arc::new(aggregator))
+        .with_server_host(&config.server.host)
+        .with_heartbeat(config.heartbeat.heartbeat())
+        .with_shutdown(shutdown.clone())
+        .with_signer(Arc::new(signer))
+        .with_coingecko(settings.coingecko())
+        .with_address_cache(settings.address_cache());
+    if let Some(tls) = settings.tls_config() {
+        client_manager = client_manager.with_tls(tls.load()?);
+    }
+    client_manager
+        .create_clients(config.tokens.clone(), tx)
+        .await?;
+
+    // Stop the clients on SIGINT/SIGTERM
+    tokio::spawn(async move {
+        match wait_for_signal().await {
+            Ok(()) => {
+                info!("Shutdown requested");
+                shutdown.trigger();
+            }
+            Err(e) => error!("{}", e),
+        }
+    });
+
+    // Run the clients asynchronously, failing if they all stopped on their own
+    client_manager.run_clients().await
+}
 
-    // Run the clients asynchronously
-    client_manager.run_clients().await;
+/// Builds the price aggregator from the configured providers, which
+/// [`Config::validate`] already checked.
+fn build_aggregator(config: &Config) -> PriceAggregator {
+    let settings = &config.client;
+    let token_rounds = config.token_batch_rounds();
+    let providers = settings
+        .providers
+        .iter()
+        .map(|name| -> Arc<dyn PriceProvider> {
+            match name.as_str() {
+                "coingecko" => Arc::new(settings.coingecko()),
+                _ => settings.defillama(&token_rounds),
+            }
+        })
+        .collect();
 
-    Ok(())
+    PriceAggregator::new(providers, settings.max_deviation_bps, settings.min_sources)
 }
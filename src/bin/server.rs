@@ -1,18 +1,133 @@
+use chrono::Duration;
+use clap::Parser;
 use dotenv::dotenv;
-use std::env;
-use suicrypto_oracle::{domain::websocket_server::WebSocketServer, AppError};
+use log::{error, info, warn};
+use std::process::ExitCode;
+use std::sync::Arc;
+use suicrypto_oracle::{
+    config::{Config, ConfigArgs},
+    domain::{
+        http_server::HttpServer,
+        price_store::PriceStore,
+        shutdown::{wait_for_signal, Shutdown},
+        signing::{AttestationSigner, ReportVerifier},
+        websocket_server::WebSocketServer,
+    },
+    infraestructure::price_history::PriceHistory,
+    AppError,
+};
+
+/// Oracle server requesting token prices from the connected clients.
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Cli {
+    #[command(flatten)]
+    config: ConfigArgs,
+}
 
 /// The entry point of the application.
+///
+/// Exits with code 0 after a SIGINT/SIGTERM shutdown and 1 if the server fails.
 #[tokio::main]
-async fn main() -> Result<(), AppError> {
+async fn main() -> ExitCode {
     dotenv().ok(); // Load environment variables from a `.env` file if it exists
     env_logger::init(); // Initialize the logger
+    let cli = Cli::parse();
+
+    match run(cli).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            error!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Runs the WebSocket server and the HTTP API until a shutdown signal is received.
+async fn run(cli: Cli) -> Result<(), AppError> {
+    // Load the configuration file, overridden by environment variables and flags
+    let config = Config::load(&cli.config)?;
+    let settings = &config.server;
+
+    // Open the price history database, keeping records forever unless a retention is set
+    let retention = settings.history_retention_days.map(Duration::days);
+    let history = PriceHistory::open(&settings.history_db_path, retention)?;
+
+    // Load the key attesting the published prices, creating one on first run
+    let attestor = AttestationSigner::load_or_generate(&settings.key_path)?;
+    info!(
+        "Attesting prices with key {} (public key {})",
+        attestor.key_id(),
+        attestor.public_key()
+    );
+
+    // Attest the published prices, continuing the rounds saved in the history, and
+    // leave stale, low-confidence or unmoved reports out of them
+    let mut store = PriceStore::new()
+        .with_attestor(Arc::new(attestor))
+        .with_last_rounds(history.last_rounds()?)
+        .with_quote_policy(config.default_quote_policy())
+        .with_publish_policy(config.default_publish_policy());
+    for (token, policy) in config.token_quote_policies() {
+        store = store.with_token_quote_policy(&token, policy);
+    }
+    for (token, policy) in config.token_publish_policies() {
+        store = store.with_token_publish_policy(&token, policy);
+    }
+
+    // Create the WebSocket server and the HTTP API sharing the price store
+    let shutdown = Shutdown::new();
+    let mut server = WebSocketServer::from_store(&settings.host, store.clone())?
+        .with_history(history.clone())
+        .with_heartbeat(config.heartbeat.heartbeat())
+        .with_shutdown(shutdown.clone())
+        .with_default_schedule(config.default_schedule());
+
+    // Poll tokens with their own interval or jitter on their own schedule
+    for (token, schedule) in config.token_schedules() {
+        server = server.with_token_schedule(&token, schedule);
+    }
+
+    // Only accept signed reports from allowlisted clients, if an allowlist is configured
+    match &settings.client_keys_path {
+        Some(path) => {
+            server = server.with_report_verifier(ReportVerifier::load_from_file(path)?);
+            info!("Accepting reports signed by the keys in {}", path);
+        }
+        None => warn!("No client key allowlist configured, accepting unsigned price reports"),
+    }
+
+    // Serve wss:// when a certificate is configured, requiring client certificates if a CA is given
+    if let Some(tls) = settings.tls_config() {
+        server = server.with_tls(tls.load()?);
+    }
+
+    let http_server = HttpServer::new(&settings.http_host, store)
+        .with_history(history)
+        .with_shutdown(shutdown.clone());
 
-    // Read the server host from environment variables
-    let server_host = env::var("SERVER_HOST").unwrap_or(String::from("127.0.0.1:8080"));
+    // Stop both servers on SIGINT/SIGTERM
+    let signal_shutdown = shutdown.clone();
+    tokio::spawn(async move {
+        match wait_for_signal().await {
+            Ok(()) => {
+                info!("Shutdown requested");
+                signal_shutdown.trigger();
+            }
+            Err(e) => error!("{}", e),
+        }
+    });
 
-    // Create and run the WebSocket server
-    let server = WebSocketServer::new(&server_host)?;
-    server.run().await?;
-    Ok(())
+    // A failing server shuts the other one down gracefully before reporting its error
+    let stop_on_error = |result: Result<(), AppError>| {
+        if result.is_err() {
+            shutdown.trigger();
+        }
+        result
+    };
+    let (server_result, http_result) =
+        tokio::join!(async { stop_on_error(server.run().await) }, async {
+            stop_on_error(http_server.run().await)
+        },);
+    server_result.and(http_result)
 }
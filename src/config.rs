@@ -1,17 +1,416 @@
+use clap::Args;
 use serde::Deserialize;
+use std::collections::HashSet;
+use std::fmt::Display;
 use std::fs::File;
 use std::io::Read;
 use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
 
+use crate::domain::heartbeat::{Heartbeat, DEFAULT_HEARTBEAT_INTERVAL, DEFAULT_HEARTBEAT_TIMEOUT};
+use crate::domain::price_provider::PriceProvider;
+use crate::domain::publish_policy::PublishPolicy;
+use crate::domain::quote_policy::QuotePolicy;
+use crate::domain::scheduler::Schedule;
+use crate::domain::websocket_server::{DEFAULT_REQUEST_INTERVAL, DEFAULT_SERVER_HOST};
+use crate::infraestructure::address_cache::{AddressCache, DEFAULT_ADDRESS_CACHE_TTL};
+use crate::infraestructure::api_client::{
+    ApiClient, DEFAULT_DEFILLAMA_BASE_URL, DEFAULT_REQUEST_TIMEOUT,
+};
+use crate::infraestructure::coingecko_client::{CoinGeckoClient, DEFAULT_COINGECKO_BASE_URL};
+use crate::infraestructure::defillama_batcher::{BatchingApiClient, DEFAULT_BATCH_ROUND};
+use crate::infraestructure::tls::{ClientTlsConfig, ServerTlsConfig};
 use crate::AppError;
 
-// Configuration struct to load token information
+/// Configuration file read when no path is given.
+pub const DEFAULT_CONFIG_PATH: &str = "config.json";
+
+/// Configuration file holding only the token list, still read when `config.json` is missing.
+const LEGACY_CONFIG_PATH: &str = "tokens.json";
+
+/// Longest accepted time setting, in seconds: one year, far beyond any sensible value
+/// but small enough for timers and timestamps never to overflow.
+pub const MAX_DURATION_SECS: u64 = 365 * 24 * 60 * 60;
+
+/// Longest accepted history retention, in days.
+pub const MAX_HISTORY_RETENTION_DAYS: i64 = 100 * 365;
+
+/// Price providers the client can query.
+pub const KNOWN_PROVIDERS: [&str; 2] = ["defillama", "coingecko"];
+
+/// Token entry as written in the configuration file.
+///
+/// Tokens can be listed by name only (`"SUI"`) or with their own settings
+/// (`{"symbol": "SUI", "coin_type": "0x2::sui::SUI", "interval_secs": 5, "max_age_secs": 300}`).
 #[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum TokenEntry {
+    Name(String),
+    Detailed {
+        symbol: String,
+        coin_type: Option<String>,
+        interval_secs: Option<u64>,
+        jitter_secs: Option<u64>,
+        max_age_secs: Option<u64>,
+        min_confidence: Option<f64>,
+        deviation_bps: Option<u32>,
+        heartbeat_secs: Option<u64>,
+    },
+}
+
+/// Configuration of a single token.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(from = "TokenEntry")]
+pub struct TokenConfig {
+    pub symbol: String,
+    /// Sui coin type of the token; looked up on CoinGecko by symbol when not set.
+    pub coin_type: Option<String>,
+    /// Time between two price requests for this token, if it overrides the server default.
+    pub interval_secs: Option<u64>,
+    /// Maximum random delay added to each request interval.
+    pub jitter_secs: Option<u64>,
+    /// Oldest a quote may be to be published, if it overrides the default.
+    pub max_age_secs: Option<u64>,
+    /// Lowest confidence a quote may have to be published, if it overrides the default.
+    pub min_confidence: Option<f64>,
+    /// Smallest price move, in basis points, worth publishing, if it overrides the default.
+    pub deviation_bps: Option<u32>,
+    /// Longest time between two published prices, if it overrides the default.
+    pub heartbeat_secs: Option<u64>,
+}
+
+impl From<TokenEntry> for TokenConfig {
+    fn from(entry: TokenEntry) -> Self {
+        match entry {
+            TokenEntry::Name(symbol) => TokenConfig {
+                symbol,
+                coin_type: None,
+                interval_secs: None,
+                jitter_secs: None,
+                max_age_secs: None,
+                min_confidence: None,
+                deviation_bps: None,
+                heartbeat_secs: None,
+            },
+            TokenEntry::Detailed {
+                symbol,
+                coin_type,
+                interval_secs,
+                jitter_secs,
+                max_age_secs,
+                min_confidence,
+                deviation_bps,
+                heartbeat_secs,
+            } => TokenConfig {
+                symbol,
+                coin_type,
+                interval_secs,
+                jitter_secs,
+                max_age_secs,
+                min_confidence,
+                deviation_bps,
+                heartbeat_secs,
+            },
+        }
+    }
+}
+
+/// TLS settings of the server; it serves `wss://` when present.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerTlsSettings {
+    pub cert_path: Option<String>,
+    pub key_path: Option<String>,
+    /// CA of the client certificates, required from every client when set.
+    pub client_ca_path: Option<String>,
+}
+
+/// Settings of the server binary.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerSettings {
+    /// Address of the WebSocket server, which clients connect to.
+    pub host: String,
+    /// Address of the HTTP API.
+    pub http_host: String,
+    /// Default time between two price requests to the same token.
+    pub request_interval_secs: u64,
+    /// File holding the key attesting the published prices.
+    pub key_path: String,
+    /// Allowlist of client public keys; unsigned reports are accepted when not set.
+    pub client_keys_path: Option<String>,
+    pub history_db_path: String,
+    /// Days of price history kept; history is kept forever when not set.
+    pub history_retention_days: Option<i64>,
+    pub tls: Option<ServerTlsSettings>,
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        Self {
+            host: DEFAULT_SERVER_HOST.to_string(),
+            http_host: "127.0.0.1:8081".to_string(),
+            request_interval_secs: DEFAULT_REQUEST_INTERVAL.as_secs(),
+            key_path: "server.key".to_string(),
+            client_keys_path: None,
+            history_db_path: "price_history.db".to_string(),
+            history_retention_days: None,
+            tls: None,
+        }
+    }
+}
+
+impl ServerSettings {
+    /// Returns the TLS configuration of the server, if it serves `wss://`.
+    pub fn tls_config(&self) -> Option<ServerTlsConfig> {
+        let tls = self.tls.as_ref()?;
+        let mut config = ServerTlsConfig::new(tls.cert_path.as_deref()?, tls.key_path.as_deref()?);
+        if let Some(ca_path) = &tls.client_ca_path {
+            config = config.with_client_ca(ca_path);
+        }
+        Some(config)
+    }
+}
+
+/// TLS settings of the client; it connects with `wss://` when present.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClientTlsSettings {
+    /// CA of the server certificate; the Mozilla roots are trusted when not set.
+    pub ca_path: Option<String>,
+    /// Client certificate, for servers requiring mutual TLS.
+    pub cert_path: Option<String>,
+    pub key_path: Option<String>,
+}
+
+/// Settings of the client binary.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClientSettings {
+    /// Price providers queried for every token.
+    pub providers: Vec<String>,
+    /// Largest deviation from the median, in basis points, of an accepted quote.
+    pub max_deviation_bps: u32,
+    /// Number of agreeing providers needed to report a price.
+    pub min_sources: usize,
+    /// File holding the key signing the price reports.
+    pub key_path: String,
+    /// Longest time a request to a price provider may take before it fails.
+    pub request_timeout_secs: u64,
+    /// Base URL of the CoinGecko API, e.g. the Pro API or a caching proxy.
+    pub coingecko_base_url: String,
+    /// Key of the CoinGecko Pro API.
+    pub coingecko_api_key: Option<String>,
+    /// Base URL of the DefiLlama coins API.
+    pub defillama_base_url: String,
+    /// Time the DefiLlama prices fetched in one batch are served; `0` fetches every
+    /// token on its own.
+    pub defillama_batch_round_ms: u64,
+    /// File caching the contract addresses resolved on CoinGecko.
+    pub address_cache_path: String,
+    /// Time a cached contract address is used before being looked up again.
+    pub address_cache_ttl_secs: u64,
+    pub tls: Option<ClientTlsSettings>,
+}
+
+impl Default for ClientSettings {
+    fn default() -> Self {
+        Self {
+            providers: vec!["defillama".to_string()],
+            max_deviation_bps: crate::application::price_aggregator::DEFAULT_MAX_DEVIATION_BPS,
+            min_sources: 1,
+            key_path: "client.key".to_string(),
+            request_timeout_secs: DEFAULT_REQUEST_TIMEOUT.as_secs(),
+            coingecko_base_url: DEFAULT_COINGECKO_BASE_URL.to_string(),
+            coingecko_api_key: None,
+            defillama_base_url: DEFAULT_DEFILLAMA_BASE_URL.to_string(),
+            defillama_batch_round_ms: DEFAULT_BATCH_ROUND.as_millis() as u64,
+            address_cache_path: "address_cache.json".to_string(),
+            address_cache_ttl_secs: DEFAULT_ADDRESS_CACHE_TTL.num_seconds() as u64,
+            tls: None,
+        }
+    }
+}
+
+impl ClientSettings {
+    /// Returns the CoinGecko client, used to look up contract addresses and as price provider.
+    pub fn coingecko(&self) -> CoinGeckoClient {
+        let client = CoinGeckoClient::new()
+            .with_base_url(&self.coingecko_base_url)
+            .with_timeout(self.request_timeout());
+        match &self.coingecko_api_key {
+            Some(api_key) => client.with_api_key(api_key),
+            None => client,
+        }
+    }
+
+    /// Returns the DefiLlama price provider, batching the requests of all tokens
+    /// unless batching is disabled.
+    ///
+    /// The tokens in `token_rounds` are served from shorter rounds, see
+    /// [`Config::token_batch_rounds`].
+    pub fn defillama(&self, token_rounds: &[(String, Duration)]) -> Arc<dyn PriceProvider> {
+        let client = ApiClient::new()
+            .with_base_url(&self.defillama_base_url)
+            .with_timeout(self.request_timeout());
+        if self.defillama_batch_round_ms == 0 {
+            return Arc::new(client);
+        }
+        let mut batcher = BatchingApiClient::new(client).with_round(self.batch_round());
+        for (token, round) in token_rounds {
+            batcher = batcher.with_token_round(token, *round);
+        }
+        Arc::new(batcher)
+    }
+
+    /// Returns how long the DefiLlama prices fetched in one batch are served.
+    pub fn batch_round(&self) -> Duration {
+        Duration::from_millis(self.defillama_batch_round_ms)
+    }
+
+    /// Returns the longest time a request to a price provider may take.
+    pub fn request_timeout(&self) -> Duration {
+        Duration::from_secs(self.request_timeout_secs)
+    }
+
+    /// Returns the cache of the contract addresses resolved on CoinGecko.
+    pub fn address_cache(&self) -> AddressCache {
+        AddressCache::open(
+            &self.address_cache_path,
+            chrono::Duration::seconds(self.address_cache_ttl_secs as i64),
+        )
+    }
+
+    /// Returns the TLS configuration of the client, if it connects with `wss://`.
+    pub fn tls_config(&self) -> Option<ClientTlsConfig> {
+        let tls = self.tls.as_ref()?;
+        let mut config = ClientTlsConfig::new();
+        if let Some(ca_path) = &tls.ca_path {
+            config = config.with_ca(ca_path);
+        }
+        if let (Some(cert_path), Some(key_path)) = (&tls.cert_path, &tls.key_path) {
+            config = config.with_client_cert(cert_path, key_path);
+        }
+        Some(config)
+    }
+}
+
+/// Ping/pong settings shared by the server and the client.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HeartbeatSettings {
+    pub interval_secs: u64,
+    pub timeout_secs: u64,
+}
+
+impl Default for HeartbeatSettings {
+    fn default() -> Self {
+        Self {
+            interval_secs: DEFAULT_HEARTBEAT_INTERVAL.as_secs(),
+            timeout_secs: DEFAULT_HEARTBEAT_TIMEOUT.as_secs(),
+        }
+    }
+}
+
+impl HeartbeatSettings {
+    /// Returns the heartbeat used by the WebSocket connections.
+    pub fn heartbeat(&self) -> Heartbeat {
+        Heartbeat::new(
+            Duration::from_secs(self.interval_secs),
+            Duration::from_secs(self.timeout_secs),
+        )
+    }
+}
+
+/// Thresholds quotes must meet to be published, unless a token has its own.
+///
+/// Checked by the client, which excludes failing quotes from its price, and by the
+/// server, which leaves failing reports out of the published price.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuoteSettings {
+    pub max_age_secs: Option<u64>,
+    pub min_confidence: Option<f64>,
+}
+
+/// When the server publishes a new price, unless a token has its own policy.
+///
+/// A report is published if the price moved more than `deviation_bps` from the
+/// published one or `heartbeat_secs` elapsed since then; without either, every
+/// report is published.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PublishSettings {
+    pub deviation_bps: Option<u32>,
+    pub heartbeat_secs: Option<u64>,
+}
+
+// Configuration of the server and the client
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct Config {
-    pub tokens: Vec<String>,
+    pub server: ServerSettings,
+    pub client: ClientSettings,
+    pub heartbeat: HeartbeatSettings,
+    pub quotes: QuoteSettings,
+    pub publishing: PublishSettings,
+    pub tokens: Vec<TokenConfig>,
+}
+
+/// Command-line flags overriding the configuration file and the environment.
+#[derive(Debug, Clone, Default, Args)]
+pub struct ConfigArgs {
+    /// Configuration file [default: config.json, or tokens.json if missing]
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<String>,
+
+    /// Address of the WebSocket server
+    #[arg(long, value_name = "HOST:PORT")]
+    pub server_host: Option<String>,
+
+    /// Address of the HTTP API
+    #[arg(long, value_name = "HOST:PORT")]
+    pub http_host: Option<String>,
+
+    /// Default time between two price requests to the same token
+    #[arg(long, value_name = "SECS")]
+    pub request_interval_secs: Option<u64>,
+
+    /// Comma-separated price providers queried by the client
+    #[arg(long, value_name = "NAMES", value_delimiter = ',')]
+    pub providers: Option<Vec<String>>,
+
+    /// Time between two pings
+    #[arg(long, value_name = "SECS")]
+    pub heartbeat_interval_secs: Option<u64>,
+
+    /// Time without hearing from a peer before its connection is dropped
+    #[arg(long, value_name = "SECS")]
+    pub heartbeat_timeout_secs: Option<u64>,
 }
 
 impl Config {
+    /// Loads the configuration with layered precedence: the configuration file, then
+    /// environment variables, then command-line flags, and validates the result.
+    pub fn load(args: &ConfigArgs) -> Result<Self, AppError> {
+        let mut config = match &args.config {
+            Some(path) => Self::load_from_file(path)?,
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => {
+                Self::load_from_file(DEFAULT_CONFIG_PATH)?
+            }
+            None if Path::new(LEGACY_CONFIG_PATH).exists() => {
+                Self::load_from_file(LEGACY_CONFIG_PATH)?
+            }
+            None => Self::default(),
+        };
+        config.apply_env(|name| std::env::var(name).ok())?;
+        config.apply_args(args);
+        config.validate()?;
+        Ok(config)
+    }
+
     /// Loads configuration from a JSON file.
     pub fn load_from_file(file_path: &str) -> Result<Self, AppError> {
         let path = Path::new(file_path);
@@ -24,6 +423,475 @@ impl Config {
 
         // Deserialize JSON content into the Config structure
         serde_json::from_str::<Config>(&contents)
-            .map_err(|e| AppError::JsonError(format!("Error deserializing JSON file: {}", e)))
+            .map_err(|e| AppError::ConfigError(format!("Error deserializing {}: {}", file_path, e)))
+    }
+
+    /// Overrides the settings given by environment variables, read with `var`.
+    pub fn apply_env<F>(&mut self, var: F) -> Result<(), AppError>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let server = &mut self.server;
+        if let Some(v) = var("SERVER_HOST") {
+            server.host = v;
+        }
+        if let Some(v) = var("HTTP_HOST") {
+            server.http_host = v;
+        }
+        if let Some(v) = var("REQUEST_INTERVAL_SECS") {
+            server.request_interval_secs = parse("REQUEST_INTERVAL_SECS", &v)?;
+        }
+        if let Some(v) = var("SERVER_KEY_PATH") {
+            server.key_path = v;
+        }
+        if let Some(v) = var("CLIENT_KEYS_PATH") {
+            server.client_keys_path = Some(v);
+        }
+        if let Some(v) = var("HISTORY_DB_PATH") {
+            server.history_db_path = v;
+        }
+        if let Some(v) = var("HISTORY_RETENTION_DAYS") {
+            server.history_retention_days = Some(parse("HISTORY_RETENTION_DAYS", &v)?);
+        }
+        let tls_paths = (
+            var("TLS_CERT_PATH"),
+            var("TLS_KEY_PATH"),
+            var("TLS_CLIENT_CA_PATH"),
+        );
+        if tls_paths != (None, None, None) {
+            let tls = server.tls.get_or_insert_with(Default::default);
+            let (cert_path, key_path, client_ca_path) = tls_paths;
+            tls.cert_path = cert_path.or(tls.cert_path.take());
+            tls.key_path = key_path.or(tls.key_path.take());
+            tls.client_ca_path = client_ca_path.or(tls.client_ca_path.take());
+        }
+
+        let client = &mut self.client;
+        if let Some(v) = var("PRICE_PROVIDERS") {
+            client.providers = split_list(&v);
+        }
+        if let Some(v) = var("MAX_DEVIATION_BPS") {
+            client.max_deviation_bps = parse("MAX_DEVIATION_BPS", &v)?;
+        }
+        if let Some(v) = var("MIN_SOURCES") {
+            client.min_sources = parse("MIN_SOURCES", &v)?;
+        }
+        if let Some(v) = var("CLIENT_KEY_PATH") {
+            client.key_path = v;
+        }
+        if let Some(v) = var("REQUEST_TIMEOUT_SECS") {
+            client.request_timeout_secs = parse("REQUEST_TIMEOUT_SECS", &v)?;
+        }
+        if let Some(v) = var("COINGECKO_BASE_URL") {
+            client.coingecko_base_url = v;
+        }
+        if let Some(v) = var("COINGECKO_API_KEY") {
+            client.coingecko_api_key = Some(v);
+        }
+        if let Some(v) = var("DEFILLAMA_BASE_URL") {
+            client.defillama_base_url = v;
+        }
+        if let Some(v) = var("DEFILLAMA_BATCH_ROUND_MS") {
+            client.defillama_batch_round_ms = parse("DEFILLAMA_BATCH_ROUND_MS", &v)?;
+        }
+        if let Some(v) = var("ADDRESS_CACHE_PATH") {
+            client.address_cache_path = v;
+        }
+        if let Some(v) = var("ADDRESS_CACHE_TTL_SECS") {
+            client.address_cache_ttl_secs = parse("ADDRESS_CACHE_TTL_SECS", &v)?;
+        }
+        if let Some(v) = var("SERVER_TLS") {
+            let enabled = match v.trim().to_lowercase().as_str() {
+                "true" | "1" => true,
+                "false" | "0" => false,
+                _ => {
+                    return Err(AppError::ConfigError(format!(
+                        "Invalid SERVER_TLS {:?}: expected true or false",
+                        v
+                    )))
+                }
+            };
+            if enabled {
+                client.tls.get_or_insert_with(Default::default);
+            } else {
+                client.tls = None;
+            }
+        }
+        if let Some(tls) = client.tls.as_mut() {
+            tls.ca_path = var("TLS_CA_PATH").or(tls.ca_path.take());
+            tls.cert_path = var("TLS_CLIENT_CERT_PATH").or(tls.cert_path.take());
+            tls.key_path = var("TLS_CLIENT_KEY_PATH").or(tls.key_path.take());
+        }
+
+        if let Some(v) = var("QUOTE_MAX_AGE_SECS") {
+            self.quotes.max_age_secs = Some(parse("QUOTE_MAX_AGE_SECS", &v)?);
+        }
+        if let Some(v) = var("QUOTE_MIN_CONFIDENCE") {
+            self.quotes.min_confidence = Some(parse("QUOTE_MIN_CONFIDENCE", &v)?);
+        }
+        if let Some(v) = var("PUBLISH_DEVIATION_BPS") {
+            self.publishing.deviation_bps = Some(parse("PUBLISH_DEVIATION_BPS", &v)?);
+        }
+        if let Some(v) = var("PUBLISH_HEARTBEAT_SECS") {
+            self.publishing.heartbeat_secs = Some(parse("PUBLISH_HEARTBEAT_SECS", &v)?);
+        }
+        if let Some(v) = var("HEARTBEAT_INTERVAL_SECS") {
+            self.heartbeat.interval_secs = parse("HEARTBEAT_INTERVAL_SECS", &v)?;
+        }
+        if let Some(v) = var("HEARTBEAT_TIMEOUT_SECS") {
+            self.heartbeat.timeout_secs = parse("HEARTBEAT_TIMEOUT_SECS", &v)?;
+        }
+        Ok(())
     }
+
+    /// Overrides the settings given as command-line flags.
+    pub fn apply_args(&mut self, args: &ConfigArgs) {
+        if let Some(host) = &args.server_host {
+            self.server.host = host.clone();
+        }
+        if let Some(host) = &args.http_host {
+            self.server.http_host = host.clone();
+        }
+        if let Some(secs) = args.request_interval_secs {
+            self.server.request_interval_secs = secs;
+        }
+        if let Some(providers) = &args.providers {
+            self.client.providers = providers.clone();
+        }
+        if let Some(secs) = args.heartbeat_interval_secs {
+            self.heartbeat.interval_secs = secs;
+        }
+        if let Some(secs) = args.heartbeat_timeout_secs {
+            self.heartbeat.timeout_secs = secs;
+        }
+    }
+
+    /// Checks that the configuration is usable, explaining the first problem found.
+    pub fn validate(&self) -> Result<(), AppError> {
+        let invalid = |msg: String| Err(AppError::ConfigError(msg));
+
+        for (name, host) in [
+            ("server.host", &self.server.host),
+            ("server.http_host", &self.server.http_host),
+        ] {
+            if host
+                .rsplit_once(':')
+                .is_none_or(|(h, port)| h.is_empty() || port.parse::<u16>().is_err())
+            {
+                return invalid(format!("{} must be HOST:PORT, got {:?}", name, host));
+            }
+        }
+        if self.server.request_interval_secs == 0 {
+            return invalid("server.request_interval_secs must be positive".to_string());
+        }
+        if self
+            .server
+            .history_retention_days
+            .is_some_and(|days| days <= 0)
+        {
+            return invalid("server.history_retention_days must be positive".to_string());
+        }
+        if let Some(days) = self
+            .server
+            .history_retention_days
+            .filter(|days| *days > MAX_HISTORY_RETENTION_DAYS)
+        {
+            return invalid(format!(
+                "server.history_retention_days must be at most {} (100 years), got {}",
+                MAX_HISTORY_RETENTION_DAYS, days
+            ));
+        }
+        if let Some(tls) = &self.server.tls {
+            if tls.cert_path.is_none() || tls.key_path.is_none() {
+                return invalid(
+                    "server.tls needs both cert_path and key_path (TLS_CERT_PATH, TLS_KEY_PATH)"
+                        .to_string(),
+                );
+            }
+        }
+
+        if self.client.providers.is_empty() {
+            return invalid("client.providers must list at least one provider".to_string());
+        }
+        if let Some(unknown) = self
+            .client
+            .providers
+            .iter()
+            .find(|p| !KNOWN_PROVIDERS.contains(&p.as_str()))
+        {
+            return invalid(format!(
+                "Unknown price provider {:?}, expected one of {}",
+                unknown,
+                KNOWN_PROVIDERS.join(", ")
+            ));
+        }
+        if self.client.min_sources == 0 || self.client.min_sources > self.client.providers.len() {
+            return invalid(format!(
+                "client.min_sources must be between 1 and the number of providers ({})",
+                self.client.providers.len()
+            ));
+        }
+        if self.client.request_timeout_secs == 0 {
+            return invalid("client.request_timeout_secs must be positive".to_string());
+        }
+        for (name, url) in [
+            ("client.coingecko_base_url", &self.client.coingecko_base_url),
+            ("client.defillama_base_url", &self.client.defillama_base_url),
+        ] {
+            if !(url.starts_with("http://") || url.starts_with("https://")) {
+                return invalid(format!("{} must be an http(s) URL, got {:?}", name, url));
+            }
+        }
+        if self.client.address_cache_ttl_secs > i64::MAX as u64 / 1000 {
+            return invalid("client.address_cache_ttl_secs is too large".to_string());
+        }
+        if let Some(tls) = &self.client.tls {
+            if tls.cert_path.is_some() != tls.key_path.is_some() {
+                return invalid(
+                    "client.tls needs both cert_path and key_path for a client certificate"
+                        .to_string(),
+                );
+            }
+        }
+
+        if self.heartbeat.interval_secs == 0 {
+            return invalid("heartbeat.interval_secs must be positive".to_string());
+        }
+        if self.heartbeat.timeout_secs <= self.heartbeat.interval_secs {
+            return invalid("heartbeat.timeout_secs must be longer than interval_secs".to_string());
+        }
+
+        if let Err(msg) =
+            check_quote_thresholds(self.quotes.max_age_secs, self.quotes.min_confidence)
+        {
+            return invalid(format!("quotes: {}", msg));
+        }
+        if self.publishing.heartbeat_secs == Some(0) {
+            return invalid("publishing.heartbeat_secs must be positive".to_string());
+        }
+
+        for (name, secs) in [
+            (
+                "server.request_interval_secs",
+                Some(self.server.request_interval_secs),
+            ),
+            (
+                "client.request_timeout_secs",
+                Some(self.client.request_timeout_secs),
+            ),
+            (
+                "heartbeat.interval_secs",
+                Some(self.heartbeat.interval_secs),
+            ),
+            ("heartbeat.timeout_secs", Some(self.heartbeat.timeout_secs)),
+            ("quotes.max_age_secs", self.quotes.max_age_secs),
+            ("publishing.heartbeat_secs", self.publishing.heartbeat_secs),
+        ] {
+            if let Err(msg) = check_duration(name, secs) {
+                return invalid(msg);
+            }
+        }
+
+        let mut symbols = HashSet::new();
+        for token in &self.tokens {
+            if token.symbol.trim().is_empty() {
+                return invalid("Token symbols must not be empty".to_string());
+            }
+            if !symbols.insert(token.symbol.to_uppercase()) {
+                return invalid(format!("Token {} is listed twice", token.symbol));
+            }
+            if let Some(coin_type) = &token.coin_type {
+                if !is_coin_type(coin_type) {
+                    return invalid(format!(
+                        "Token {}: coin_type must look like 0x<address>::<module>::<name>, got {:?}",
+                        token.symbol, coin_type
+                    ));
+                }
+            }
+            if let Err(msg) = check_quote_thresholds(token.max_age_secs, token.min_confidence) {
+                return invalid(format!("Token {}: {}", token.symbol, msg));
+            }
+            if token.heartbeat_secs == Some(0) {
+                return invalid(format!(
+                    "Token {}: heartbeat_secs must be positive",
+                    token.symbol
+                ));
+            }
+            if token.interval_secs == Some(0) {
+                return invalid(format!(
+                    "Token {}: interval_secs must be positive",
+                    token.symbol
+                ));
+            }
+            for (name, secs) in [
+                ("interval_secs", token.interval_secs),
+                ("jitter_secs", token.jitter_secs),
+                ("max_age_secs", token.max_age_secs),
+                ("heartbeat_secs", token.heartbeat_secs),
+            ] {
+                if let Err(msg) = check_duration(name, secs) {
+                    return invalid(format!("Token {}: {}", token.symbol, msg));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the names of the configured tokens.
+    pub fn token_names(&self) -> Vec<String> {
+        self.tokens.iter().map(|t| t.symbol.clone()).collect()
+    }
+
+    /// Returns the polling schedule of the tokens without their own.
+    pub fn default_schedule(&self) -> Schedule {
+        Schedule::new(
+            Duration::from_secs(self.server.request_interval_secs),
+            Duration::ZERO,
+        )
+    }
+
+    /// Returns the quote policy of the tokens without their own.
+    pub fn default_quote_policy(&self) -> QuotePolicy {
+        QuotePolicy::new(
+            self.quotes.max_age_secs.map(Duration::from_secs),
+            self.quotes.min_confidence,
+        )
+    }
+
+    /// Returns the tokens polled too often for the DefiLlama batch round, with a round
+    /// capped at half their polling interval so a request is never answered with the
+    /// price fetched for the previous one.
+    pub fn token_batch_rounds(&self) -> Vec<(String, Duration)> {
+        let round = self.client.batch_round();
+        self.tokens
+            .iter()
+            .filter_map(|t| {
+                let interval = t.interval_secs.unwrap_or(self.server.request_interval_secs);
+                let cap = Duration::from_secs(interval) / 2;
+                (cap < round).then(|| (t.symbol.clone(), cap))
+            })
+            .collect()
+    }
+
+    /// Returns the tokens with their own quote thresholds, with their quote policy.
+    pub fn token_quote_policies(&self) -> Vec<(String, QuotePolicy)> {
+        let default = self.default_quote_policy();
+        self.tokens
+            .iter()
+            .filter(|t| t.max_age_secs.is_some() || t.min_confidence.is_some())
+            .map(|t| {
+                let policy = QuotePolicy::new(
+                    t.max_age_secs.map(Duration::from_secs).or(default.max_age),
+                    t.min_confidence.or(default.min_confidence),
+                );
+                (t.symbol.clone(), policy)
+            })
+            .collect()
+    }
+
+    /// Returns the publishing policy of the tokens without their own.
+    pub fn default_publish_policy(&self) -> PublishPolicy {
+        PublishPolicy::new(
+            self.publishing.deviation_bps,
+            self.publishing.heartbeat_secs.map(Duration::from_secs),
+        )
+    }
+
+    /// Returns the tokens with their own deviation or heartbeat, with their publishing policy.
+    pub fn token_publish_policies(&self) -> Vec<(String, PublishPolicy)> {
+        let default = self.default_publish_policy();
+        self.tokens
+            .iter()
+            .filter(|t| t.deviation_bps.is_some() || t.heartbeat_secs.is_some())
+            .map(|t| {
+                let policy = PublishPolicy::new(
+                    t.deviation_bps.or(default.deviation_bps),
+                    t.heartbeat_secs
+                        .map(Duration::from_secs)
+                        .or(default.heartbeat),
+                );
+                (t.symbol.clone(), policy)
+            })
+            .collect()
+    }
+
+    /// Returns the tokens with their own interval or jitter, with their polling schedule.
+    pub fn token_schedules(&self) -> Vec<(String, Schedule)> {
+        let default = self.default_schedule();
+        self.tokens
+            .iter()
+            .filter(|t| t.interval_secs.is_some() || t.jitter_secs.is_some())
+            .map(|t| {
+                let interval = t
+                    .interval_secs
+                    .map(Duration::from_secs)
+                    .unwrap_or(default.interval);
+                let jitter = Duration::from_secs(t.jitter_secs.unwrap_or(0));
+                (t.symbol.clone(), Schedule::new(interval, jitter))
+            })
+            .collect()
+    }
+}
+
+/// Checks the thresholds of a quote policy.
+fn check_quote_thresholds(
+    max_age_secs: Option<u64>,
+    min_confidence: Option<f64>,
+) -> Result<(), String> {
+    if max_age_secs == Some(0) {
+        return Err("max_age_secs must be positive".to_string());
+    }
+    if min_confidence.is_some_and(|c| !(0.0..=1.0).contains(&c)) {
+        return Err("min_confidence must be between 0 and 1".to_string());
+    }
+    Ok(())
+}
+
+/// Checks that a time setting, if set, is at most [`MAX_DURATION_SECS`].
+fn check_duration(name: &str, secs: Option<u64>) -> Result<(), String> {
+    match secs {
+        Some(secs) if secs > MAX_DURATION_SECS => Err(format!(
+            "{} must be at most {} (one year), got {}",
+            name, MAX_DURATION_SECS, secs
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Parses the value of an environment variable.
+fn parse<T>(name: &str, value: &str) -> Result<T, AppError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    value
+        .trim()
+        .parse()
+        .map_err(|e| AppError::ConfigError(format!("Invalid {} {:?}: {}", name, value, e)))
+}
+
+/// Checks that `value` is a Sui coin type such as `0x2::sui::SUI`.
+fn is_coin_type(value: &str) -> bool {
+    let parts: Vec<&str> = value.split("::").collect();
+    let [address, module, name] = parts.as_slice() else {
+        return false;
+    };
+    let is_identifier = |s: &str| {
+        s.chars().next().is_some_and(|c| c.is_ascii_alphabetic())
+            && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    };
+    address.strip_prefix("0x").is_some_and(|hex| {
+        !hex.is_empty() && hex.len() <= 64 && hex.chars().all(|c| c.is_ascii_hexdigit())
+    }) && is_identifier(module)
+        && is_identifier(name)
+}
+
+/// Splits a comma-separated list, dropping empty items.
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
 }
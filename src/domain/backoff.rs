@@ -0,0 +1,42 @@
+// backoff.rs
+use rand::Rng;
+use std::time::Duration;
+
+/// Default delay before the first reconnection attempt.
+pub const DEFAULT_INITIAL_DELAY: Duration = Duration::from_millis(500);
+
+/// Default upper bound of the delay between two reconnection attempts.
+pub const DEFAULT_MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Exponential backoff with jitter, used between reconnection attempts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    /// Delay before the first retry.
+    pub initial: Duration,
+    /// Upper bound of the delay, however many attempts failed.
+    pub max: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new(DEFAULT_INITIAL_DELAY, DEFAULT_MAX_DELAY)
+    }
+}
+
+impl Backoff {
+    /// Creates a new backoff policy.
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Self { initial, max }
+    }
+
+    /// Returns the delay before the given retry, counting from 1.
+    ///
+    /// The delay doubles with every attempt up to `max`, and a random jitter picks a value
+    /// between half and all of it so that clients dropped together do not retry together.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(31);
+        let delay = self.initial.saturating_mul(1u32 << exponent).min(self.max);
+        let half = delay / 2;
+        half + rand::thread_rng().gen_range(Duration::ZERO..=delay - half)
+    }
+}
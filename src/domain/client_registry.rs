@@ -0,0 +1,145 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+use super::signing::ReportSignature;
+use crate::AppError;
+
+/// Registration sent by a client right after connecting.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClientRegistration {
+    /// Token name the client reports prices for.
+    pub token: String,
+    /// Sui coin type of the token.
+    pub contract_address: String,
+    /// Identifier of the client instance, unique per token.
+    pub client_id: String,
+    /// Version of the client software.
+    pub client_version: String,
+    /// Signature of the client, required by servers that only accept signed reports.
+    #[serde(flatten, default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<ReportSignature>,
+}
+
+impl ClientRegistration {
+    /// Creates a new, unsigned registration.
+    pub fn new(
+        token: String,
+        contract_address: String,
+        client_id: String,
+        client_version: String,
+    ) -> Self {
+        Self {
+            token,
+            contract_address,
+            client_id,
+            client_version,
+            signature: None,
+        }
+    }
+}
+
+/// A client currently connected to the server.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RegisteredClient {
+    #[serde(flatten)]
+    pub registration: ClientRegistration,
+    pub connected_at: DateTime<Utc>,
+    /// Identifier of the connection the client registered on.
+    #[serde(skip)]
+    pub connection: u64,
+}
+
+/// Registry of the clients connected to the server, grouped by token.
+///
+/// Cloning the registry is cheap; all clones share the same underlying data.
+#[derive(Debug, Clone, Default)]
+pub struct ClientRegistry {
+    clients: Arc<RwLock<HashMap<String, HashMap<String, RegisteredClient>>>>,
+    next_connection: Arc<AtomicU64>,
+}
+
+impl ClientRegistry {
+    /// Creates a new, empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a client for its token.
+    ///
+    /// A client reconnecting with the same id replaces its previous registration.
+    ///
+    /// # Returns
+    /// * The identifier of the new registration's connection, see [`Self::unregister`].
+    pub fn register(&self, registration: ClientRegistration) -> Result<u64, AppError> {
+        if registration.token.trim().is_empty() || registration.client_id.trim().is_empty() {
+            return Err(AppError::RegistrationError(
+                "Token and client id must not be empty".to_string(),
+            ));
+        }
+
+        let mut clients = self.clients.write().unwrap_or_else(|e| e.into_inner());
+        let token_clients = clients
+            .entry(registration.token.to_uppercase())
+            .or_default();
+
+        if let Some(existing) = token_clients
+            .values()
+            .find(|c| c.registration.contract_address != registration.contract_address)
+        {
+            return Err(AppError::RegistrationError(format!(
+                "Token {} is already registered with contract address {}",
+                registration.token, existing.registration.contract_address
+            )));
+        }
+
+        let connection = self.next_connection.fetch_add(1, Ordering::Relaxed);
+        token_clients.insert(
+            registration.client_id.clone(),
+            RegisteredClient {
+                registration,
+                connected_at: Utc::now(),
+                connection,
+            },
+        );
+        Ok(connection)
+    }
+
+    /// Removes a client registration made on the given connection.
+    ///
+    /// Registrations made since by the same client on another connection are kept.
+    pub fn unregister(&self, token: &str, client_id: &str, connection: u64) {
+        let mut clients = self.clients.write().unwrap_or_else(|e| e.into_inner());
+        let key = token.to_uppercase();
+        if let Some(token_clients) = clients.get_mut(&key) {
+            if token_clients
+                .get(client_id)
+                .is_some_and(|c| c.connection == connection)
+            {
+                token_clients.remove(client_id);
+            }
+            if token_clients.is_empty() {
+                clients.remove(&key);
+            }
+        }
+    }
+
+    /// Returns the clients registered for a token.
+    pub fn clients(&self, token: &str) -> Vec<RegisteredClient> {
+        let clients = self.clients.read().unwrap_or_else(|e| e.into_inner());
+        clients
+            .get(&token.to_uppercase())
+            .map(|c| c.values().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Returns the tokens with at least one registered client, sorted by name.
+    pub fn tokens(&self) -> Vec<String> {
+        let clients = self.clients.read().unwrap_or_else(|e| e.into_inner());
+        let mut tokens: Vec<String> = clients.keys().cloned().collect();
+        tokens.sort();
+        tokens
+    }
+}
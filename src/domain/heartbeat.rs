@@ -0,0 +1,43 @@
+// heartbeat.rs
+use tokio::time::{interval_at, Duration, Instant, Interval, MissedTickBehavior};
+
+/// Default time between two pings.
+pub const DEFAULT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Default time without hearing from the peer after which it is considered dead.
+pub const DEFAULT_HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(45);
+
+/// WebSocket ping/pong settings used to detect dead peers.
+///
+/// Every `interval` a ping is sent to the peer; if nothing at all, pong or
+/// message, was received from it for `timeout`, the connection is dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Heartbeat {
+    pub interval: Duration,
+    pub timeout: Duration,
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Self::new(DEFAULT_HEARTBEAT_INTERVAL, DEFAULT_HEARTBEAT_TIMEOUT)
+    }
+}
+
+impl Heartbeat {
+    /// Creates new heartbeat settings.
+    pub fn new(interval: Duration, timeout: Duration) -> Self {
+        Self { interval, timeout }
+    }
+
+    /// Returns a timer ticking once per interval, starting one interval from now.
+    pub(crate) fn ticker(&self) -> Interval {
+        let mut ticker = interval_at(Instant::now() + self.interval, self.interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        ticker
+    }
+
+    /// Returns whether a peer last heard from at `last_seen` should be considered dead.
+    pub(crate) fn is_expired(&self, last_seen: Instant) -> bool {
+        last_seen.elapsed() > self.timeout
+    }
+}
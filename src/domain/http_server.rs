@@ -0,0 +1,246 @@
+// http_server.rs
+use super::price_store::{ExcludedReport, PriceRecord, PriceStore};
+use super::shutdown::Shutdown;
+use crate::{
+    infraestructure::price_history::{downsample, PriceBucket, PriceHistory},
+    AppError,
+};
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use chrono::{DateTime, Duration, Utc};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpListener;
+
+/// Window queried by the history endpoint when `from` is not given.
+const DEFAULT_HISTORY_WINDOW: Duration = Duration::hours(24);
+
+/// Bucket size used by the history endpoint when `interval` is not given.
+const DEFAULT_HISTORY_INTERVAL: &str = "5m";
+
+/// Latest price of a token as returned by the REST API.
+#[derive(Debug, Serialize)]
+pub struct PriceResponse {
+    #[serde(flatten)]
+    pub record: PriceRecord,
+    /// Seconds elapsed since the quote timestamp.
+    pub age_secs: i64,
+}
+
+impl From<PriceRecord> for PriceResponse {
+    fn from(record: PriceRecord) -> Self {
+        let age_secs = (Utc::now() - record.timestamp).num_seconds();
+        PriceResponse { record, age_secs }
+    }
+}
+
+/// Query parameters of the history endpoint.
+#[derive(Debug, Deserialize)]
+pub struct HistoryQuery {
+    /// Start of the range (RFC 3339), defaults to 24 hours before `to`.
+    pub from: Option<DateTime<Utc>>,
+    /// End of the range (RFC 3339), defaults to now.
+    pub to: Option<DateTime<Utc>>,
+    /// Bucket size such as `30s`, `5m`, `1h` or `1d`.
+    pub interval: Option<String>,
+}
+
+/// Downsampled price history of a token as returned by the REST API.
+#[derive(Debug, Serialize)]
+pub struct HistoryResponse {
+    pub symbol: String,
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub interval_secs: i64,
+    pub points: Vec<PriceBucket>,
+}
+
+/// Body returned by the REST API when a request fails.
+#[derive(Debug, Serialize)]
+struct ErrorResponse {
+    error: String,
+    /// Latest report left out of a price that was never published.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    excluded: Option<ExcludedReport>,
+}
+
+fn error_response(status: StatusCode, message: String) -> Response {
+    let body = ErrorResponse {
+        error: message,
+        excluded: None,
+    };
+    (status, Json(body)).into_response()
+}
+
+/// State shared by the REST API handlers.
+#[derive(Debug, Clone)]
+struct ApiState {
+    store: PriceStore,
+    history: Option<PriceHistory>,
+}
+
+/// An HTTP server exposing the prices held by the oracle.
+pub struct HttpServer {
+    address: String,
+    store: PriceStore,
+    history: Option<PriceHistory>,
+    shutdown: Shutdown,
+}
+
+impl HttpServer {
+    /// Creates a new HTTP server.
+    ///
+    /// # Arguments
+    /// * `address` - A string slice containing the address to bind the server.
+    /// * `store` - The store the served prices are read from.
+    pub fn new(address: &str, store: PriceStore) -> Self {
+        Self {
+            address: address.to_string(),
+            store,
+            history: None,
+            shutdown: Shutdown::new(),
+        }
+    }
+
+    /// Serves historical prices from the given history.
+    pub fn with_history(mut self, history: PriceHistory) -> Self {
+        self.history = Some(history);
+        self
+    }
+
+    /// Stops serving, after in-flight requests complete, once `shutdown` is triggered.
+    pub fn with_shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    /// Builds the router serving the REST API.
+    pub fn router(&self) -> Router {
+        Router::new()
+            .route("/prices", get(list_prices))
+            .route("/prices/{symbol}", get(get_price))
+            .route("/prices/{symbol}/history", get(get_history))
+            .with_state(ApiState {
+                store: self.store.clone(),
+                history: self.history.clone(),
+            })
+    }
+
+    /// Starts the HTTP server and serves requests until it fails or is shut down.
+    pub async fn run(&self) -> Result<(), AppError> {
+        let listener = TcpListener::bind(&self.address)
+            .await
+            .map_err(|e| AppError::TcpError(e.to_string()))?;
+
+        info!("HTTP API listening on {}", &self.address);
+
+        let shutdown = self.shutdown.clone();
+        axum::serve(listener, self.router())
+            .with_graceful_shutdown(async move { shutdown.wait().await })
+            .await
+            .map_err(|e| AppError::HttpError(e.to_string()))
+    }
+}
+
+/// `GET /prices`: latest quote of every token.
+async fn list_prices(State(state): State<ApiState>) -> Json<Vec<PriceResponse>> {
+    Json(
+        state
+            .store
+            .all()
+            .into_iter()
+            .map(PriceResponse::from)
+            .collect(),
+    )
+}
+
+/// `GET /prices/{symbol}`: latest quote of a single token.
+async fn get_price(State(state): State<ApiState>, Path(symbol): Path<String>) -> Response {
+    match state.store.get(&symbol) {
+        Some(record) => Json(PriceResponse::from(record)).into_response(),
+        None => {
+            let body = ErrorResponse {
+                error: format!("No price available for {}", symbol),
+                excluded: state.store.unpublished(&symbol).map(|u| u.excluded),
+            };
+            (StatusCode::NOT_FOUND, Json(body)).into_response()
+        }
+    }
+}
+
+/// `GET /prices/{symbol}/history?from=&to=&interval=`: bucketed historical prices.
+async fn get_history(
+    State(state): State<ApiState>,
+    Path(symbol): Path<String>,
+    Query(query): Query<HistoryQuery>,
+) -> Response {
+    let Some(history) = state.history else {
+        return error_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Price history is not enabled".to_string(),
+        );
+    };
+
+    let to = query.to.unwrap_or_else(Utc::now);
+    let from = query.from.unwrap_or(to - DEFAULT_HISTORY_WINDOW);
+    if from > to {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            "'from' must not be after 'to'".to_string(),
+        );
+    }
+
+    let interval_param = query
+        .interval
+        .unwrap_or(DEFAULT_HISTORY_INTERVAL.to_string());
+    let Some(interval) = parse_interval(&interval_param) else {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            format!("Invalid interval: {}", interval_param),
+        );
+    };
+
+    // SQLite calls block, keep them off the async workers
+    let query_symbol = symbol.clone();
+    let points = tokio::task::spawn_blocking(move || history.range(&query_symbol, from, to))
+        .await
+        .unwrap_or_else(|e| Err(AppError::DatabaseError(e.to_string())));
+    match points {
+        Ok(points) => Json(HistoryResponse {
+            symbol: symbol.to_uppercase(),
+            from,
+            to,
+            interval_secs: interval.num_seconds(),
+            points: downsample(&points, interval),
+        })
+        .into_response(),
+        Err(e) => {
+            error!("{}", e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+        }
+    }
+}
+
+/// Parses an interval such as `30s`, `5m`, `1h`, `1d` or a bare number of seconds,
+/// or returns `None` if it is malformed or too long to represent.
+fn parse_interval(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let (amount, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => value.split_at(index),
+        None => (value, "s"),
+    };
+    let amount: i64 = amount.parse().ok().filter(|a| *a > 0)?;
+
+    match unit {
+        "s" => Duration::try_seconds(amount),
+        "m" => Duration::try_minutes(amount),
+        "h" => Duration::try_hours(amount),
+        "d" => Duration::try_days(amount),
+        _ => None,
+    }
+}
@@ -1,3 +1,16 @@
+pub mod backoff;
+pub mod client_registry;
+pub mod heartbeat;
+pub mod http_server;
+pub mod price_provider;
+pub mod price_requester;
+pub mod price_store;
+pub mod protocol;
+pub mod publish_policy;
+pub mod quote_policy;
+pub mod scheduler;
+pub mod shutdown;
+pub mod signing;
 pub mod websocket_connection;
 pub mod websocket_handler;
 pub mod websocket_server;
@@ -0,0 +1,73 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+
+use super::quote_policy::QuoteFlag;
+use crate::AppError;
+
+/// Reference to a token on the Sui network.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TokenRef {
+    /// Token name as listed in the configuration (e.g. `SUI`).
+    pub name: String,
+    /// Sui coin type of the token (e.g. `0x2::sui::SUI`).
+    pub contract_address: String,
+}
+
+impl TokenRef {
+    /// Creates a new token reference.
+    pub fn new(name: String, contract_address: String) -> Self {
+        TokenRef {
+            name,
+            contract_address,
+        }
+    }
+}
+
+/// A price quote returned by a price provider.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PriceQuote {
+    pub symbol: String,
+    pub price: f64,
+    pub timestamp: DateTime<Utc>,
+    /// Number of sources that agreed on this price (1 for a single provider).
+    #[serde(default = "default_sources")]
+    pub sources: usize,
+    /// Confidence of the source in the price, between 0 and 1, if it reports one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f64>,
+    /// Thresholds of the token's quote policy the price fails.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub flags: Vec<QuoteFlag>,
+}
+
+impl PriceQuote {
+    /// Creates a quote backed by a single source.
+    pub fn new(symbol: String, price: f64, timestamp: DateTime<Utc>) -> Self {
+        PriceQuote {
+            symbol,
+            price,
+            timestamp,
+            sources: 1,
+            confidence: None,
+            flags: Vec::new(),
+        }
+    }
+}
+
+fn default_sources() -> usize {
+    1
+}
+
+/// A source of token prices.
+///
+/// Implementations are shared between client tasks, so they must be thread-safe.
+#[async_trait]
+pub trait PriceProvider: Debug + Send + Sync {
+    /// Short name of the provider, used in logs.
+    fn name(&self) -> &str;
+
+    /// Fetches the current price of the given token.
+    async fn fetch(&self, token: &TokenRef) -> Result<PriceQuote, AppError>;
+}
@@ -0,0 +1,99 @@
+use tokio::sync::broadcast;
+
+use super::client_registry::{ClientRegistration, ClientRegistry};
+use crate::AppError;
+
+/// Clients a price request is addressed to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RequestTarget {
+    /// Every registered client.
+    All,
+    /// The clients serving any of these tokens.
+    Tokens(Vec<String>),
+    /// A single client of a token.
+    Client { token: String, client_id: String },
+}
+
+impl RequestTarget {
+    /// Returns whether a client with the given registration is addressed by this target.
+    pub fn matches(&self, registration: &ClientRegistration) -> bool {
+        match self {
+            RequestTarget::All => true,
+            RequestTarget::Tokens(tokens) => tokens
+                .iter()
+                .any(|t| t.eq_ignore_ascii_case(&registration.token)),
+            RequestTarget::Client { token, client_id } => {
+                token.eq_ignore_ascii_case(&registration.token)
+                    && *client_id == registration.client_id
+            }
+        }
+    }
+}
+
+/// Handle used to ask connected clients for their prices.
+///
+/// Cloning the requester is cheap; all clones address the same connections.
+#[derive(Debug, Clone)]
+pub struct PriceRequester {
+    sender: broadcast::Sender<RequestTarget>,
+    registry: ClientRegistry,
+}
+
+impl PriceRequester {
+    /// Creates a new requester over the given channel and client registry.
+    pub fn new(sender: broadcast::Sender<RequestTarget>, registry: ClientRegistry) -> Self {
+        Self { sender, registry }
+    }
+
+    /// Subscribes a connection to the requests sent from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<RequestTarget> {
+        self.sender.subscribe()
+    }
+
+    /// Asks every registered client for its price.
+    pub fn request_all(&self) -> Result<(), AppError> {
+        if self.registry.tokens().is_empty() {
+            return Err(AppError::BroadcastError(
+                "No clients registered".to_string(),
+            ));
+        }
+        self.send(RequestTarget::All)
+    }
+
+    /// Asks the clients serving `token` for its price.
+    pub fn request_token(&self, token: &str) -> Result<(), AppError> {
+        if self.registry.clients(token).is_empty() {
+            return Err(AppError::BroadcastError(format!(
+                "No clients registered for token {}",
+                token
+            )));
+        }
+        self.send(RequestTarget::Tokens(vec![token.to_string()]))
+    }
+
+    /// Asks a single client of `token` for its price.
+    pub fn request_client(&self, token: &str, client_id: &str) -> Result<(), AppError> {
+        if !self
+            .registry
+            .clients(token)
+            .iter()
+            .any(|c| c.registration.client_id == client_id)
+        {
+            return Err(AppError::BroadcastError(format!(
+                "Client {} is not registered for token {}",
+                client_id, token
+            )));
+        }
+        self.send(RequestTarget::Client {
+            token: token.to_string(),
+            client_id: client_id.to_string(),
+        })
+    }
+
+    fn send(&self, target: RequestTarget) -> Result<(), AppError> {
+        self.sender
+            .send(target)
+            .map(|_| ())
+            .map_err(|_| AppError::BroadcastError("No clients listening".to_string()))
+    }
+}
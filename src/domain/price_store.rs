@@ -0,0 +1,327 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use tokio::sync::broadcast;
+
+use super::price_provider::PriceQuote;
+use super::publish_policy::PublishPolicy;
+use super::quote_policy::{QuoteFlag, QuotePolicy};
+use super::signing::{Attestation, AttestationSigner, ReportSignature};
+
+/// Price report sent by a client for the token it serves.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PriceReport {
+    /// Sui coin type of the reported token.
+    pub contract_address: String,
+    #[serde(flatten)]
+    pub quote: PriceQuote,
+    /// Signature of the client, if it signs its reports.
+    #[serde(flatten, default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<ReportSignature>,
+}
+
+impl PriceReport {
+    /// Creates a new, unsigned report.
+    pub fn new(contract_address: String, quote: PriceQuote) -> Self {
+        Self {
+            contract_address,
+            quote,
+            signature: None,
+        }
+    }
+}
+
+/// Latest price known for a symbol, as reported by a client.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PriceRecord {
+    pub symbol: String,
+    pub contract_address: String,
+    pub price: f64,
+    /// Timestamp of the quote at its source.
+    pub timestamp: DateTime<Utc>,
+    /// Number of sources that agreed on the price.
+    pub sources: usize,
+    /// Time at which the server received the report.
+    pub received_at: DateTime<Utc>,
+    /// Server signature of the price, if the server attests the prices it publishes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attestation: Option<Attestation>,
+    /// Latest report left out of the published price since it was published.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub excluded: Option<ExcludedReport>,
+}
+
+impl PriceRecord {
+    /// Creates the unattested record of a report received at `received_at`.
+    pub fn from_report(report: PriceReport, received_at: DateTime<Utc>) -> Self {
+        let PriceReport {
+            contract_address,
+            quote,
+            ..
+        } = report;
+        Self {
+            symbol: quote.symbol.to_uppercase(),
+            contract_address,
+            price: quote.price,
+            timestamp: quote.timestamp,
+            sources: quote.sources,
+            received_at,
+            attestation: None,
+            excluded: None,
+        }
+    }
+}
+
+/// A price report that failed the token's quote policy, and why.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExcludedReport {
+    pub price: f64,
+    /// Timestamp of the quote at its source.
+    pub timestamp: DateTime<Utc>,
+    /// Time at which the server received the report.
+    pub received_at: DateTime<Utc>,
+    pub flags: Vec<QuoteFlag>,
+}
+
+/// A symbol without a published price, and the latest report left out of it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UnpublishedPrice {
+    pub symbol: String,
+    pub contract_address: String,
+    pub excluded: ExcludedReport,
+}
+
+/// Shared, concurrent store holding the latest quote per symbol.
+///
+/// Cloning the store is cheap; all clones share the same underlying data.
+/// Every published update is also sent to the store's subscribers.
+#[derive(Debug, Clone)]
+pub struct PriceStore {
+    prices: Arc<RwLock<HashMap<String, PriceRecord>>>,
+    /// Symbols whose every report was excluded so far; only locked while holding `prices`.
+    unpublished: Arc<RwLock<HashMap<String, UnpublishedPrice>>>,
+    updates: broadcast::Sender<PriceRecord>,
+    attestor: Option<Arc<AttestationSigner>>,
+    /// Last attested round of each symbol before the store was created.
+    last_rounds: Arc<HashMap<String, u64>>,
+    default_policy: QuotePolicy,
+    policies: Arc<HashMap<String, QuotePolicy>>,
+    default_publish_policy: PublishPolicy,
+    publish_policies: Arc<HashMap<String, PublishPolicy>>,
+}
+
+impl Default for PriceStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PriceStore {
+    /// Creates a new, empty price store.
+    pub fn new() -> Self {
+        let (updates, _) = broadcast::channel(256);
+        Self {
+            prices: Arc::new(RwLock::new(HashMap::new())),
+            unpublished: Arc::new(RwLock::new(HashMap::new())),
+            updates,
+            attestor: None,
+            last_rounds: Arc::new(HashMap::new()),
+            default_policy: QuotePolicy::default(),
+            policies: Arc::new(HashMap::new()),
+            default_publish_policy: PublishPolicy::default(),
+            publish_policies: Arc::new(HashMap::new()),
+        }
+    }
+
+    /// Attests every accepted price with the given key.
+    ///
+    /// Only the store returned, and the clones made from it, attest prices.
+    pub fn with_attestor(mut self, attestor: Arc<AttestationSigner>) -> Self {
+        self.attestor = Some(attestor);
+        self
+    }
+
+    /// Continues the attestation rounds of each symbol from the given last rounds, such
+    /// as the ones saved in the price history, so rounds are never attested twice.
+    pub fn with_last_rounds(mut self, rounds: HashMap<String, u64>) -> Self {
+        self.last_rounds = Arc::new(
+            rounds
+                .into_iter()
+                .map(|(symbol, round)| (symbol.to_uppercase(), round))
+                .collect(),
+        );
+        self
+    }
+
+    /// Sets the quote policy of the tokens without their own.
+    ///
+    /// Like [`Self::with_attestor`], only applies to the store returned and its clones.
+    pub fn with_quote_policy(mut self, policy: QuotePolicy) -> Self {
+        self.default_policy = policy;
+        self
+    }
+
+    /// Sets the quote policy of a specific token.
+    pub fn with_token_quote_policy(mut self, token: &str, policy: QuotePolicy) -> Self {
+        Arc::make_mut(&mut self.policies).insert(token.to_uppercase(), policy);
+        self
+    }
+
+    /// Sets the publishing policy of the tokens without their own.
+    pub fn with_publish_policy(mut self, policy: PublishPolicy) -> Self {
+        self.default_publish_policy = policy;
+        self
+    }
+
+    /// Sets the publishing policy of a specific token.
+    pub fn with_token_publish_policy(mut self, token: &str, policy: PublishPolicy) -> Self {
+        Arc::make_mut(&mut self.publish_policies).insert(token.to_uppercase(), policy);
+        self
+    }
+
+    /// Returns the flags of a report: the ones set by the client, followed by the
+    /// thresholds of the token's quote policy it fails.
+    ///
+    /// Reports with flags must not be published, see [`Self::exclude`].
+    pub fn check(&self, report: &PriceReport) -> Vec<QuoteFlag> {
+        let policy = self
+            .policies
+            .get(&report.quote.symbol.to_uppercase())
+            .copied()
+            .unwrap_or(self.default_policy);
+        let mut flags = report.quote.flags.clone();
+        for flag in policy.check(&report.quote, Utc::now()) {
+            if !flags.iter().any(|f| f.reason == flag.reason) {
+                flags.push(flag);
+            }
+        }
+        flags
+    }
+
+    /// Records a report left out of the published price, so consumers can see why
+    /// the price was not updated.
+    ///
+    /// Symbols without a published price keep their excluded report apart, see
+    /// [`Self::unpublished`].
+    ///
+    /// # Returns
+    /// * `Some(PriceRecord)` with the published record, now showing the excluded report.
+    /// * `None` if no price is published for the symbol yet, or a newer quote is.
+    pub fn exclude(&self, report: PriceReport, flags: Vec<QuoteFlag>) -> Option<PriceRecord> {
+        let key = report.quote.symbol.to_uppercase();
+        let excluded = ExcludedReport {
+            price: report.quote.price,
+            timestamp: report.quote.timestamp,
+            received_at: Utc::now(),
+            flags,
+        };
+        let mut prices = self.prices.write().unwrap_or_else(|e| e.into_inner());
+        let Some(record) = prices.get_mut(&key) else {
+            let mut unpublished = self.unpublished.write().unwrap_or_else(|e| e.into_inner());
+            unpublished.insert(
+                key.clone(),
+                UnpublishedPrice {
+                    symbol: key,
+                    contract_address: report.contract_address,
+                    excluded,
+                },
+            );
+            return None;
+        };
+        if record.timestamp > report.quote.timestamp {
+            return None;
+        }
+
+        record.excluded = Some(excluded);
+        let record = record.clone();
+        drop(prices);
+
+        let _ = self.updates.send(record.clone());
+        Some(record)
+    }
+
+    /// Subscribes to the records published by the store from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<PriceRecord> {
+        self.updates.subscribe()
+    }
+
+    /// Records a price report sent by a client, received at time `now`.
+    ///
+    /// Reports older than the one already stored for the same symbol are ignored,
+    /// and so are the ones the token's publishing policy holds back.
+    ///
+    /// # Returns
+    /// * `Some(PriceRecord)` with the stored record if the report was published.
+    /// * `None` if a newer quote is already stored, or the price moved too little
+    ///   since it was published.
+    pub fn update(&self, report: PriceReport, now: DateTime<Utc>) -> Option<PriceRecord> {
+        let mut record = PriceRecord::from_report(report, now);
+        let key = record.symbol.clone();
+        let mut prices = self.prices.write().unwrap_or_else(|e| e.into_inner());
+
+        let publish_policy = self
+            .publish_policies
+            .get(&key)
+            .copied()
+            .unwrap_or(self.default_publish_policy);
+
+        if let Some(current) = prices.get(&key) {
+            if current.timestamp > record.timestamp
+                || !publish_policy.should_publish(current, record.price, now)
+            {
+                return None;
+            }
+        }
+        let round_id = prices
+            .get(&key)
+            .and_then(|current| current.attestation.as_ref())
+            .map(|a| a.round_id)
+            .or_else(|| self.last_rounds.get(&key).copied())
+            .unwrap_or(0)
+            + 1;
+
+        if let Some(attestor) = &self.attestor {
+            record.attestation = Some(attestor.attest(&record, round_id));
+        }
+        self.unpublished
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&key);
+        prices.insert(key, record.clone());
+        drop(prices);
+
+        // Nobody may be subscribed, which is not an error
+        let _ = self.updates.send(record.clone());
+        Some(record)
+    }
+
+    /// Returns the latest record for a symbol, if any.
+    pub fn get(&self, symbol: &str) -> Option<PriceRecord> {
+        let prices = self.prices.read().unwrap_or_else(|e| e.into_inner());
+        prices.get(&symbol.to_uppercase()).cloned()
+    }
+
+    /// Returns the latest record of every symbol, sorted by symbol.
+    pub fn all(&self) -> Vec<PriceRecord> {
+        let prices = self.prices.read().unwrap_or_else(|e| e.into_inner());
+        let mut records: Vec<PriceRecord> = prices.values().cloned().collect();
+        records.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+        records
+    }
+
+    /// Returns why a symbol has no published price, if all its reports were excluded.
+    pub fn unpublished(&self, symbol: &str) -> Option<UnpublishedPrice> {
+        let unpublished = self.unpublished.read().unwrap_or_else(|e| e.into_inner());
+        unpublished.get(&symbol.to_uppercase()).cloned()
+    }
+
+    /// Returns every symbol without a published price because its reports were
+    /// excluded, sorted by symbol.
+    pub fn all_unpublished(&self) -> Vec<UnpublishedPrice> {
+        let unpublished = self.unpublished.read().unwrap_or_else(|e| e.into_inner());
+        let mut records: Vec<UnpublishedPrice> = unpublished.values().cloned().collect();
+        records.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+        records
+    }
+}
@@ -0,0 +1,131 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::client_registry::ClientRegistration;
+use super::price_store::{PriceRecord, PriceReport, UnpublishedPrice};
+use crate::AppError;
+
+/// Version of the wire protocol spoken by this build.
+///
+/// Messages carrying a different version are rejected.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Reason carried by an [`ProtocolMessage::Error`] message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The message could not be parsed.
+    MalformedMessage,
+    /// The message uses a protocol version this peer does not speak.
+    UnsupportedVersion,
+    /// The message is valid but not expected in the current state.
+    UnexpectedMessage,
+    /// The client could not obtain a price from its upstream sources.
+    UpstreamError,
+    /// The registration or report was rejected by the server.
+    Rejected,
+}
+
+/// Messages exchanged between the oracle server, its clients and consumers.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ProtocolMessage {
+    /// Client to server: first message of a price-reporting client.
+    Register(ClientRegistration),
+    /// Server to client: report the current price of your token.
+    PriceRequest,
+    /// Client to server: price of the client's token.
+    PriceReport(PriceReport),
+    /// Either direction: a request could not be served.
+    Error { code: ErrorCode, message: String },
+    /// Either direction: the message of the given type was accepted.
+    Ack { of: String },
+    /// Consumer to server: receive the prices of these symbols.
+    Subscribe { symbols: Vec<String> },
+    /// Server to consumer: current prices of the subscribed symbols, and why the
+    /// subscribed symbols without a price have none.
+    Snapshot {
+        prices: Vec<PriceRecord>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        unpublished: Vec<UnpublishedPrice>,
+    },
+    /// Server to consumer: new price of a subscribed symbol.
+    PriceUpdate(PriceRecord),
+}
+
+impl ProtocolMessage {
+    /// Builds an error message.
+    pub fn error(code: ErrorCode, message: impl Into<String>) -> Self {
+        ProtocolMessage::Error {
+            code,
+            message: message.into(),
+        }
+    }
+
+    /// Returns the wire name of the message type.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ProtocolMessage::Register(_) => "register",
+            ProtocolMessage::PriceRequest => "price_request",
+            ProtocolMessage::PriceReport(_) => "price_report",
+            ProtocolMessage::Error { .. } => "error",
+            ProtocolMessage::Ack { .. } => "ack",
+            ProtocolMessage::Subscribe { .. } => "subscribe",
+            ProtocolMessage::Snapshot { .. } => "snapshot",
+            ProtocolMessage::PriceUpdate(_) => "price_update",
+        }
+    }
+}
+
+/// A protocol message together with the protocol version it was written with.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Envelope {
+    pub version: u32,
+    #[serde(flatten)]
+    pub message: ProtocolMessage,
+}
+
+/// Serializes a message into its versioned JSON wire format.
+pub fn encode(message: &ProtocolMessage) -> Result<String, AppError> {
+    let envelope = Envelope {
+        version: PROTOCOL_VERSION,
+        message: message.clone(),
+    };
+    serde_json::to_string(&envelope)
+        .map_err(|e| AppError::JsonError(format!("Error serializing message: {}", e)))
+}
+
+/// Parses a message from its versioned JSON wire format.
+///
+/// # Returns
+/// * `Err(AppError::ProtocolVersionError)` if the message was written with another protocol version.
+/// * `Err(AppError::ProtocolError)` if the message is malformed.
+pub fn decode(text: &str) -> Result<ProtocolMessage, AppError> {
+    let json: Value = serde_json::from_str(text)
+        .map_err(|e| AppError::ProtocolError(format!("Invalid JSON: {}", e)))?;
+
+    let version = json
+        .get("version")
+        .and_then(|v| v.as_u64())
+        .ok_or(AppError::ProtocolError(
+            "Missing protocol version".to_string(),
+        ))?;
+    if version != u64::from(PROTOCOL_VERSION) {
+        return Err(AppError::ProtocolVersionError(format!(
+            "Unsupported protocol version {} (expected {})",
+            version, PROTOCOL_VERSION
+        )));
+    }
+
+    serde_json::from_value::<Envelope>(json)
+        .map(|envelope| envelope.message)
+        .map_err(|e| AppError::ProtocolError(format!("Invalid message: {}", e)))
+}
+
+/// Maps a decoding error to the code reported back to the peer.
+pub fn error_code(error: &AppError) -> ErrorCode {
+    match error {
+        AppError::ProtocolVersionError(_) => ErrorCode::UnsupportedVersion,
+        _ => ErrorCode::MalformedMessage,
+    }
+}
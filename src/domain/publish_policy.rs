@@ -0,0 +1,45 @@
+// publish_policy.rs
+use chrono::{DateTime, Utc};
+use std::time::Duration;
+
+use super::price_store::PriceRecord;
+
+/// When a new official value of a token is published, like the deviation threshold
+/// and heartbeat of on-chain oracle feeds.
+///
+/// A report is published if the price moved more than `deviation_bps` from the
+/// published one, or if `heartbeat` elapsed since it was published. The default
+/// policy publishes every report.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PublishPolicy {
+    /// Smallest price move, in basis points of the published price, worth publishing.
+    pub deviation_bps: Option<u32>,
+    /// Longest time a published price is kept without publishing a new one.
+    pub heartbeat: Option<Duration>,
+}
+
+impl PublishPolicy {
+    /// Creates a new policy.
+    pub fn new(deviation_bps: Option<u32>, heartbeat: Option<Duration>) -> Self {
+        Self {
+            deviation_bps,
+            heartbeat,
+        }
+    }
+
+    /// Returns whether `price`, reported at time `now`, replaces the `published` record.
+    pub fn should_publish(&self, published: &PriceRecord, price: f64, now: DateTime<Utc>) -> bool {
+        if self.deviation_bps.is_none() && self.heartbeat.is_none() {
+            return true;
+        }
+
+        let moved = self.deviation_bps.is_some_and(|bps| {
+            published.price == 0.0
+                || ((price - published.price) / published.price).abs() * 10_000.0 > f64::from(bps)
+        });
+        let expired = self.heartbeat.is_some_and(|heartbeat| {
+            (now - published.received_at).to_std().unwrap_or_default() >= heartbeat
+        });
+        moved || expired
+    }
+}
@@ -0,0 +1,79 @@
+// quote_policy.rs
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use super::price_provider::PriceQuote;
+
+/// Why a quote is not fit to be published.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FlagReason {
+    /// The quote is older than the token's maximum age.
+    Stale,
+    /// The source's confidence in the quote is below the token's minimum.
+    LowConfidence,
+}
+
+/// A problem found with a quote, carried in price reports and shown to consumers.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuoteFlag {
+    pub reason: FlagReason,
+    pub message: String,
+}
+
+/// Thresholds a quote must meet to be published.
+///
+/// The default policy accepts every quote.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct QuotePolicy {
+    /// Oldest a quote may be, measured from its timestamp at the source.
+    pub max_age: Option<Duration>,
+    /// Lowest confidence a quote may have; quotes from sources that do not report
+    /// a confidence are accepted.
+    pub min_confidence: Option<f64>,
+}
+
+impl QuotePolicy {
+    /// Creates a new policy.
+    pub fn new(max_age: Option<Duration>, min_confidence: Option<f64>) -> Self {
+        Self {
+            max_age,
+            min_confidence,
+        }
+    }
+
+    /// Returns the thresholds the quote fails at time `now`, if any.
+    pub fn check(&self, quote: &PriceQuote, now: DateTime<Utc>) -> Vec<QuoteFlag> {
+        let mut flags = Vec::new();
+
+        if let Some(max_age) = self.max_age {
+            let age = (now - quote.timestamp).to_std().unwrap_or_default();
+            if age > max_age {
+                flags.push(QuoteFlag {
+                    reason: FlagReason::Stale,
+                    message: format!(
+                        "Quote of {} is {}s old, more than {}s",
+                        quote.symbol,
+                        age.as_secs(),
+                        max_age.as_secs()
+                    ),
+                });
+            }
+        }
+
+        if let (Some(min_confidence), Some(confidence)) = (self.min_confidence, quote.confidence) {
+            if confidence < min_confidence {
+                flags.push(QuoteFlag {
+                    reason: FlagReason::LowConfidence,
+                    message: format!(
+                        "Quote of {} has confidence {}, less than {}",
+                        quote.symbol, confidence, min_confidence
+                    ),
+                });
+            }
+        }
+
+        flags
+    }
+}
@@ -0,0 +1,134 @@
+// scheduler.rs
+use super::client_registry::ClientRegistry;
+use super::price_requester::PriceRequester;
+use crate::AppError;
+use log::{debug, warn};
+use rand::Rng;
+use std::collections::HashMap;
+use tokio::time::{sleep_until, Duration, Instant};
+
+/// Longest time the scheduler sleeps, so newly registered tokens are picked up promptly.
+const MAX_IDLE: Duration = Duration::from_secs(1);
+
+/// Polling schedule of a token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Schedule {
+    /// Time between two price requests.
+    pub interval: Duration,
+    /// Maximum random delay added to each interval.
+    pub jitter: Duration,
+}
+
+impl Schedule {
+    /// Creates a new schedule.
+    pub fn new(interval: Duration, jitter: Duration) -> Self {
+        Self { interval, jitter }
+    }
+
+    /// Returns the delay until the next request: the interval plus a random jitter.
+    pub fn next_delay(&self) -> Duration {
+        self.interval + random_up_to(self.jitter)
+    }
+
+    /// Returns the delay before the first request, spread over one interval so
+    /// that tokens registered together are not all requested at once.
+    fn initial_delay(&self) -> Duration {
+        random_up_to(self.interval)
+    }
+}
+
+fn random_up_to(max: Duration) -> Duration {
+    if max.is_zero() {
+        return Duration::ZERO;
+    }
+    rand::thread_rng().gen_range(Duration::ZERO..=max)
+}
+
+/// Requests the price of every registered token according to its own schedule.
+#[derive(Debug)]
+pub struct Scheduler {
+    requester: PriceRequester,
+    registry: ClientRegistry,
+    default_schedule: Schedule,
+    schedules: HashMap<String, Schedule>,
+}
+
+impl Scheduler {
+    /// Creates a new scheduler.
+    ///
+    /// # Arguments
+    /// * `requester` - The handle used to send price requests.
+    /// * `registry` - The registry of connected clients, whose tokens are polled.
+    /// * `default_schedule` - Schedule of the tokens without their own.
+    /// * `schedules` - Schedules of specific tokens, keyed by token name.
+    pub fn new(
+        requester: PriceRequester,
+        registry: ClientRegistry,
+        default_schedule: Schedule,
+        schedules: HashMap<String, Schedule>,
+    ) -> Self {
+        let schedules = schedules
+            .into_iter()
+            .map(|(token, schedule)| (token.to_uppercase(), schedule))
+            .collect();
+        Self {
+            requester,
+            registry,
+            default_schedule,
+            schedules,
+        }
+    }
+
+    /// Returns the schedule of a token.
+    pub fn schedule_for(&self, token: &str) -> Schedule {
+        self.schedules
+            .get(&token.to_uppercase())
+            .copied()
+            .unwrap_or(self.default_schedule)
+    }
+
+    /// Runs the scheduler until the task is cancelled.
+    pub async fn run(self) {
+        let mut next_due: HashMap<String, Instant> = HashMap::new();
+        let mut idle_reported = false;
+
+        loop {
+            let now = Instant::now();
+            let tokens = self.registry.tokens();
+
+            if tokens.is_empty() && !idle_reported {
+                warn!(
+                    "{}",
+                    AppError::BroadcastError("No clients registered".to_string())
+                );
+            }
+            idle_reported = tokens.is_empty();
+
+            // Forget tokens whose clients are all gone
+            next_due.retain(|token, _| tokens.contains(token));
+
+            for token in tokens {
+                let schedule = self.schedule_for(&token);
+                let due = next_due
+                    .entry(token.clone())
+                    .or_insert_with(|| now + schedule.initial_delay());
+
+                if *due <= now {
+                    debug!("Requesting price of {}", token);
+                    if let Err(e) = self.requester.request_token(&token) {
+                        warn!("{}", e);
+                    }
+                    *due = now + schedule.next_delay();
+                }
+            }
+
+            let wake = next_due
+                .values()
+                .min()
+                .copied()
+                .unwrap_or(now + MAX_IDLE)
+                .min(now + MAX_IDLE);
+            sleep_until(wake).await;
+        }
+    }
+}
@@ -0,0 +1,67 @@
+// shutdown.rs
+use crate::AppError;
+use std::sync::Arc;
+use tokio::sync::watch;
+
+/// Handle used to ask every task of the process to stop.
+///
+/// Cloning the handle is cheap; triggering any clone is seen by all of them.
+#[derive(Debug, Clone)]
+pub struct Shutdown {
+    sender: Arc<watch::Sender<bool>>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Shutdown {
+    /// Creates a new handle that has not been triggered.
+    pub fn new() -> Self {
+        let (sender, _) = watch::channel(false);
+        Self {
+            sender: Arc::new(sender),
+        }
+    }
+
+    /// Asks every task holding this handle to stop.
+    pub fn trigger(&self) {
+        self.sender.send_replace(true);
+    }
+
+    /// Returns whether shutdown has been requested.
+    pub fn is_triggered(&self) -> bool {
+        *self.sender.borrow()
+    }
+
+    /// Waits until shutdown is requested, returning immediately if it already was.
+    pub async fn wait(&self) {
+        let mut receiver = self.sender.subscribe();
+        // The sender lives as long as `self`, so waiting cannot fail
+        let _ = receiver.wait_for(|triggered| *triggered).await;
+    }
+}
+
+/// Waits for SIGINT (Ctrl+C) or, on Unix, SIGTERM.
+pub async fn wait_for_signal() -> Result<(), AppError> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate =
+            signal(SignalKind::terminate()).map_err(|e| AppError::SignalError(e.to_string()))?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result.map_err(|e| AppError::SignalError(e.to_string())),
+            _ = terminate.recv() => Ok(()),
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        tokio::signal::ctrl_c()
+            .await
+            .map_err(|e| AppError::SignalError(e.to_string()))
+    }
+}
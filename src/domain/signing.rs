@@ -0,0 +1,461 @@
+// signing.rs
+use chrono::{DateTime, Utc};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use super::client_registry::ClientRegistration;
+use super::price_provider::PriceQuote;
+use super::price_store::{PriceRecord, PriceReport};
+use crate::AppError;
+
+/// Prefix of every signed report, so report signatures cannot be replayed as anything else.
+const REPORT_DOMAIN: &str = "suicrypto-oracle/price-report/v2";
+
+/// Prefix of every signed registration, kept apart from the reports for the same reason.
+const REGISTRATION_DOMAIN: &str = "suicrypto-oracle/registration/v1";
+
+/// Prefix of every signed attestation, kept apart from the reports for the same reason.
+const ATTESTATION_DOMAIN: &str = "suicrypto-oracle/price-attestation/v1";
+
+/// Longest time between the signing of a report, read from its nonce, and its
+/// verification by the server, either way to allow for clock skew.
+pub const DEFAULT_NONCE_WINDOW: Duration = Duration::from_secs(300);
+
+/// Signature attached by a client to a price report.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReportSignature {
+    /// Number increasing with every report of a token, so a captured report cannot be sent again.
+    /// It is the signing time in microseconds since the epoch, unless reports are signed faster.
+    pub nonce: u64,
+    /// Hex-encoded Ed25519 public key of the client.
+    pub public_key: String,
+    /// Hex-encoded Ed25519 signature of the report.
+    pub signature: String,
+}
+
+/// Returns the bytes signed for a report: its token, contract address, price,
+/// timestamp, number of sources, confidence, flags and nonce.
+pub fn signing_payload(contract_address: &str, quote: &PriceQuote, nonce: u64) -> Vec<u8> {
+    let confidence = quote.confidence.map(|c| c.to_string()).unwrap_or_default();
+    let flags: Vec<String> = quote
+        .flags
+        .iter()
+        .map(|f| format!("{:?}:{}", f.reason, f.message))
+        .collect();
+    format!(
+        "{}|{}|{}|{}|{}|{}|{}|{:?}|{}",
+        REPORT_DOMAIN,
+        quote.symbol.to_uppercase(),
+        contract_address,
+        quote.price,
+        quote.timestamp.timestamp_millis(),
+        quote.sources,
+        confidence,
+        flags,
+        nonce
+    )
+    .into_bytes()
+}
+
+fn report_payload(report: &PriceReport, nonce: u64) -> Vec<u8> {
+    signing_payload(&report.contract_address, &report.quote, nonce)
+}
+
+/// Returns the bytes signed for a registration: its token, contract address, client id
+/// and nonce.
+pub fn registration_payload(registration: &ClientRegistration, nonce: u64) -> Vec<u8> {
+    format!(
+        "{}|{}|{}|{}|{}",
+        REGISTRATION_DOMAIN,
+        registration.token.to_uppercase(),
+        registration.contract_address,
+        registration.client_id,
+        nonce
+    )
+    .into_bytes()
+}
+
+/// Ed25519 keypair used by a client to sign its reports.
+#[derive(Debug)]
+pub struct ReportSigner {
+    key: SigningKey,
+    last_nonce: AtomicU64,
+}
+
+impl ReportSigner {
+    /// Creates a signer from a 32-byte secret key.
+    pub fn new(secret: [u8; 32]) -> Self {
+        Self {
+            key: SigningKey::from_bytes(&secret),
+            last_nonce: AtomicU64::new(0),
+        }
+    }
+
+    /// Creates a signer with a new random keypair.
+    pub fn generate() -> Self {
+        Self::new(SigningKey::generate(&mut rand::rngs::OsRng).to_bytes())
+    }
+
+    /// Loads the hex-encoded secret key stored at `path`, generating and saving a new one
+    /// if the file does not exist.
+    pub fn load_or_generate(path: &str) -> Result<Self, AppError> {
+        Ok(Self::new(load_or_generate_key(path)?.to_bytes()))
+    }
+
+    /// Returns the hex-encoded public key to add to the server allowlist.
+    pub fn public_key(&self) -> String {
+        encode_public_key(&self.key.verifying_key())
+    }
+
+    /// Signs a report with the next nonce and attaches the signature to it.
+    pub fn sign(&self, mut report: PriceReport) -> PriceReport {
+        let nonce = self.next_nonce();
+        let signature = self.key.sign(&report_payload(&report, nonce));
+        report.signature = Some(ReportSignature {
+            nonce,
+            public_key: self.public_key(),
+            signature: hex::encode(signature.to_bytes()),
+        });
+        report
+    }
+
+    /// Signs a registration with the next nonce and attaches the signature to it, so
+    /// servers enforcing signed reports let the client register.
+    pub fn sign_registration(&self, mut registration: ClientRegistration) -> ClientRegistration {
+        let nonce = self.next_nonce();
+        let signature = self.key.sign(&registration_payload(&registration, nonce));
+        registration.signature = Some(ReportSignature {
+            nonce,
+            public_key: self.public_key(),
+            signature: hex::encode(signature.to_bytes()),
+        });
+        registration
+    }
+
+    /// Returns a nonce greater than any returned before, based on the current time so
+    /// that it keeps increasing across restarts.
+    fn next_nonce(&self) -> u64 {
+        let now = Utc::now().timestamp_micros().max(0) as u64;
+        let mut last = self.last_nonce.load(Ordering::Relaxed);
+        loop {
+            let nonce = now.max(last + 1);
+            match self.last_nonce.compare_exchange_weak(
+                last,
+                nonce,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return nonce,
+                Err(current) => last = current,
+            }
+        }
+    }
+}
+
+/// Verifies report signatures against an allowlist of client public keys.
+///
+/// Cloning the verifier is cheap; all clones share the same replay protection.
+#[derive(Debug, Clone)]
+pub struct ReportVerifier {
+    keys: Arc<HashMap<String, VerifyingKey>>,
+    /// Last nonce accepted per public key and contract address, as a client signs the
+    /// reports of all its tokens with one key but sends them over separate connections.
+    last_nonces: Arc<Mutex<HashMap<(String, String), u64>>>,
+    nonce_window: Duration,
+}
+
+impl ReportVerifier {
+    /// Creates a verifier accepting the given hex-encoded public keys.
+    pub fn new(public_keys: &[String]) -> Result<Self, AppError> {
+        let mut keys = HashMap::new();
+        for public_key in public_keys {
+            let key = decode_public_key(public_key)?;
+            keys.insert(encode_public_key(&key), key);
+        }
+        Ok(Self {
+            keys: Arc::new(keys),
+            last_nonces: Arc::new(Mutex::new(HashMap::new())),
+            nonce_window: DEFAULT_NONCE_WINDOW,
+        })
+    }
+
+    /// Accepts reports signed up to `window` away from the server clock instead of
+    /// [`DEFAULT_NONCE_WINDOW`].
+    pub fn with_nonce_window(mut self, window: Duration) -> Self {
+        self.nonce_window = window;
+        self
+    }
+
+    /// Loads the allowlist from a file with one hex-encoded public key per line.
+    ///
+    /// Empty lines and lines starting with `#` are ignored.
+    pub fn load_from_file(path: &str) -> Result<Self, AppError> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| AppError::FileError(format!("Error reading allowlist {}: {}", path, e)))?;
+        let keys: Vec<String> = contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_string)
+            .collect();
+        Self::new(&keys)
+    }
+
+    /// Checks that a report is signed by an allowlisted key with a nonce greater than the
+    /// last one accepted from that key for the same contract address.
+    ///
+    /// The last nonces are only kept in memory, so the nonce must also be within the
+    /// nonce window of the server clock: reports captured before a restart cannot be
+    /// replayed once the window has passed.
+    pub fn verify(&self, report: &PriceReport) -> Result<(), AppError> {
+        let signed = report
+            .signature
+            .as_ref()
+            .ok_or_else(|| AppError::SignatureError("Report is not signed".to_string()))?;
+        self.check(
+            "report",
+            signed,
+            &report_payload(report, signed.nonce),
+            report.contract_address.clone(),
+        )
+    }
+
+    /// Checks that a registration is signed by an allowlisted key, like a report.
+    ///
+    /// Registration nonces are tracked apart from report nonces.
+    pub fn verify_registration(&self, registration: &ClientRegistration) -> Result<(), AppError> {
+        let signed = registration
+            .signature
+            .as_ref()
+            .ok_or_else(|| AppError::SignatureError("Registration is not signed".to_string()))?;
+        self.check(
+            "registration",
+            signed,
+            &registration_payload(registration, signed.nonce),
+            format!("register|{}", registration.contract_address),
+        )
+    }
+
+    /// Checks the signature of the `kind` message `payload`, and that its nonce is fresh
+    /// and greater than the last one accepted from the same key in the given scope.
+    fn check(
+        &self,
+        kind: &str,
+        signed: &ReportSignature,
+        payload: &[u8],
+        scope: String,
+    ) -> Result<(), AppError> {
+        let public_key = signed.public_key.to_lowercase();
+        let key = self.keys.get(&public_key).ok_or_else(|| {
+            AppError::SignatureError(format!("Public key {} is not allowed", signed.public_key))
+        })?;
+
+        let bytes: [u8; 64] = hex::decode(&signed.signature)
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| AppError::SignatureError("Malformed signature".to_string()))?;
+        key.verify(payload, &Signature::from_bytes(&bytes))
+            .map_err(|_| AppError::SignatureError(format!("Invalid {} signature", kind)))?;
+
+        let now = Utc::now().timestamp_micros().max(0) as u64;
+        let window = u64::try_from(self.nonce_window.as_micros()).unwrap_or(u64::MAX);
+        if signed.nonce.abs_diff(now) > window {
+            return Err(AppError::SignatureError(format!(
+                "Nonce {} is more than {:?} away from the server clock",
+                signed.nonce, self.nonce_window
+            )));
+        }
+
+        // Only a valid signature may advance the nonce
+        let mut last_nonces = self.last_nonces.lock().unwrap_or_else(|e| e.into_inner());
+        let last = last_nonces.entry((public_key, scope)).or_insert(0);
+        if signed.nonce <= *last {
+            return Err(AppError::SignatureError(format!(
+                "Nonce {} was already used",
+                signed.nonce
+            )));
+        }
+        *last = signed.nonce;
+        Ok(())
+    }
+}
+
+/// Attestation attached by the server to every price it publishes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Attestation {
+    /// Number of prices published for the symbol so far, starting at 1.
+    pub round_id: u64,
+    /// Identifier of the server key, see [`key_id`].
+    pub key_id: String,
+    /// Hex-encoded Ed25519 signature of the symbol, price, timestamp and round id.
+    pub signature: String,
+}
+
+/// Returns the bytes signed for a published price: its symbol, price, timestamp and round id.
+pub fn attestation_payload(
+    symbol: &str,
+    price: f64,
+    timestamp: DateTime<Utc>,
+    round_id: u64,
+) -> Vec<u8> {
+    format!(
+        "{}|{}|{}|{}|{}",
+        ATTESTATION_DOMAIN,
+        symbol.to_uppercase(),
+        price,
+        timestamp.timestamp_millis(),
+        round_id
+    )
+    .into_bytes()
+}
+
+/// Returns the identifier of a hex-encoded public key: its first 16 hex characters.
+pub fn key_id(public_key: &str) -> String {
+    public_key.to_lowercase().chars().take(16).collect()
+}
+
+/// Ed25519 keypair used by the server to attest the prices it publishes.
+#[derive(Debug)]
+pub struct AttestationSigner {
+    key: SigningKey,
+    key_id: String,
+}
+
+impl AttestationSigner {
+    /// Creates a signer from a 32-byte secret key.
+    pub fn new(secret: [u8; 32]) -> Self {
+        let key = SigningKey::from_bytes(&secret);
+        let key_id = key_id(&encode_public_key(&key.verifying_key()));
+        Self { key, key_id }
+    }
+
+    /// Creates a signer with a new random keypair.
+    pub fn generate() -> Self {
+        Self::new(SigningKey::generate(&mut rand::rngs::OsRng).to_bytes())
+    }
+
+    /// Loads the hex-encoded secret key stored at `path`, generating and saving a new one
+    /// if the file does not exist.
+    pub fn load_or_generate(path: &str) -> Result<Self, AppError> {
+        Ok(Self::new(load_or_generate_key(path)?.to_bytes()))
+    }
+
+    /// Returns the hex-encoded public key consumers verify attestations with.
+    pub fn public_key(&self) -> String {
+        encode_public_key(&self.key.verifying_key())
+    }
+
+    /// Returns the identifier of the key, included in every attestation.
+    pub fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    /// Attests a price as the given round of its symbol.
+    pub fn attest(&self, record: &PriceRecord, round_id: u64) -> Attestation {
+        let payload = attestation_payload(&record.symbol, record.price, record.timestamp, round_id);
+        Attestation {
+            round_id,
+            key_id: self.key_id.clone(),
+            signature: hex::encode(self.key.sign(&payload).to_bytes()),
+        }
+    }
+}
+
+/// Checks offline that a published price was attested by the server key `public_key`
+/// (hex-encoded), as found in price updates, snapshots and REST responses.
+pub fn verify_attestation(record: &PriceRecord, public_key: &str) -> Result<(), AppError> {
+    let attestation = record
+        .attestation
+        .as_ref()
+        .ok_or_else(|| AppError::SignatureError("Price is not attested".to_string()))?;
+    if attestation.key_id != key_id(public_key) {
+        return Err(AppError::SignatureError(format!(
+            "Price is attested by key {}, not {}",
+            attestation.key_id,
+            key_id(public_key)
+        )));
+    }
+
+    let key = decode_public_key(public_key)?;
+    let bytes: [u8; 64] = hex::decode(&attestation.signature)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| AppError::SignatureError("Malformed signature".to_string()))?;
+    let payload = attestation_payload(
+        &record.symbol,
+        record.price,
+        record.timestamp,
+        attestation.round_id,
+    );
+    key.verify(&payload, &Signature::from_bytes(&bytes))
+        .map_err(|_| AppError::SignatureError("Invalid attestation signature".to_string()))
+}
+
+fn encode_public_key(key: &VerifyingKey) -> String {
+    hex::encode(key.to_bytes())
+}
+
+fn decode_public_key(public_key: &str) -> Result<VerifyingKey, AppError> {
+    let bytes: [u8; 32] = hex::decode(public_key.trim())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| AppError::SignatureError(format!("Invalid public key: {}", public_key)))?;
+    VerifyingKey::from_bytes(&bytes)
+        .map_err(|e| AppError::SignatureError(format!("Invalid public key {}: {}", public_key, e)))
+}
+
+/// Loads the hex-encoded secret key stored at `path`, generating and saving a new one
+/// if the file does not exist.
+///
+/// New key files are only readable by their owner.
+fn load_or_generate_key(path: &str) -> Result<SigningKey, AppError> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+
+    match options.open(path) {
+        Ok(mut file) => {
+            let key = SigningKey::generate(&mut rand::rngs::OsRng);
+            file.write_all(hex::encode(key.to_bytes()).as_bytes())
+                .map_err(|e| {
+                    AppError::FileError(format!("Error writing key file {}: {}", path, e))
+                })?;
+            info!(
+                "Generated a new key in {} with public key {}",
+                path,
+                encode_public_key(&key.verifying_key())
+            );
+            return Ok(key);
+        }
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+        Err(e) => {
+            return Err(AppError::FileError(format!(
+                "Error creating key file {}: {}",
+                path, e
+            )))
+        }
+    }
+
+    let contents = fs::read_to_string(path)
+        .map_err(|e| AppError::FileError(format!("Error reading key file {}: {}", path, e)))?;
+    let secret: [u8; 32] = hex::decode(contents.trim())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| {
+            AppError::SignatureError(format!(
+                "Key file {} must contain a hex-encoded 32-byte secret key",
+                path
+            ))
+        })?;
+    Ok(SigningKey::from_bytes(&secret))
+}
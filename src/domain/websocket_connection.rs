@@ -1,15 +1,68 @@
 // websocket_connection.rs
-use crate::AppError;
-use futures_util::{SinkExt, StreamExt};
-use log::info;
+use super::client_registry::{ClientRegistration, ClientRegistry};
+use super::heartbeat::Heartbeat;
+use super::price_requester::RequestTarget;
+use super::price_store::{PriceRecord, PriceReport, PriceStore};
+use super::protocol::{self, ErrorCode, ProtocolMessage};
+use super::shutdown::Shutdown;
+use super::signing::ReportVerifier;
+use crate::{infraestructure::price_history::PriceHistory, AppError};
+use chrono::Utc;
+use futures_util::{SinkExt, Stream, StreamExt};
+use log::{debug, error, info, warn};
+use std::collections::HashSet;
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::net::TcpStream;
-use tokio::sync::broadcast;
-use tokio_tungstenite::{accept_async, tungstenite::protocol::Message};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time::{timeout, Duration, Instant};
+use tokio_rustls::TlsAcceptor;
+use tokio_tungstenite::{
+    accept_async,
+    tungstenite::protocol::{frame::coding::CloseCode, CloseFrame, Message},
+    WebSocketStream,
+};
+
+/// How long a closing connection waits for the client's last reports and close frame.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Role taken by a connection after its first message.
+enum Role {
+    /// Neither registered nor subscribed yet.
+    Pending,
+    /// Price-reporting client serving a single token, with its registry connection id.
+    Reporter(ClientRegistration, u64),
+    /// Downstream consumer following some symbols.
+    Consumer {
+        symbols: HashSet<String>,
+        updates: broadcast::Receiver<PriceRecord>,
+    },
+}
+
+/// State of a connection while its messages are being handled.
+struct Session {
+    store: PriceStore,
+    history: Option<PriceHistory>,
+    registry: ClientRegistry,
+    verifier: Option<ReportVerifier>,
+    role: Role,
+}
 
 /// A WebSocket connection handler.
+///
+/// A connection becomes a price-reporting client once it sends a
+/// [`ProtocolMessage::Register`], or a consumer once it sends a
+/// [`ProtocolMessage::Subscribe`]. Only registered clients receive price
+/// requests, and only consumers are pushed price updates.
 pub struct WebSocketConnection {
     stream: TcpStream,
-    receiver: broadcast::Receiver<String>,
+    receiver: broadcast::Receiver<RequestTarget>,
+    store: PriceStore,
+    history: Option<PriceHistory>,
+    registry: ClientRegistry,
+    heartbeat: Heartbeat,
+    shutdown: Shutdown,
+    verifier: Option<ReportVerifier>,
+    tls: Option<TlsAcceptor>,
 }
 
 impl WebSocketConnection {
@@ -17,57 +70,463 @@ impl WebSocketConnection {
     ///
     /// # Arguments
     /// * `stream` - The TCP stream representing the WebSocket connection.
-    /// * `receiver` - The broadcast receiver to listen for messages from the server.
+    /// * `receiver` - The broadcast receiver to listen for price requests from the server.
+    /// * `store` - The store where price reports received from the client are kept.
+    /// * `history` - Optional persistent history where accepted reports are appended.
+    /// * `registry` - The registry where the client is recorded while connected.
     ///
     /// # Returns
     /// * A `WebSocketConnection` instance to handle the connection.
-    pub fn new(stream: TcpStream, receiver: broadcast::Receiver<String>) -> Self {
-        Self { stream, receiver }
+    pub fn new(
+        stream: TcpStream,
+        receiver: broadcast::Receiver<RequestTarget>,
+        store: PriceStore,
+        history: Option<PriceHistory>,
+        registry: ClientRegistry,
+    ) -> Self {
+        Self {
+            stream,
+            receiver,
+            store,
+            history,
+            registry,
+            heartbeat: Heartbeat::default(),
+            shutdown: Shutdown::new(),
+            verifier: None,
+            tls: None,
+        }
+    }
+
+    /// Runs the connection over TLS, performing the handshake with the given acceptor.
+    pub fn with_tls(mut self, acceptor: TlsAcceptor) -> Self {
+        self.tls = Some(acceptor);
+        self
+    }
+
+    /// Only accepts reports signed by one of the verifier's allowlisted keys.
+    pub fn with_report_verifier(mut self, verifier: ReportVerifier) -> Self {
+        self.verifier = Some(verifier);
+        self
+    }
+
+    /// Closes the connection once `shutdown` is triggered.
+    pub fn with_shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    /// Sets the ping interval and the timeout after which a silent client is dropped.
+    pub fn with_heartbeat(mut self, heartbeat: Heartbeat) -> Self {
+        self.heartbeat = heartbeat;
+        self
     }
 
     /// Handles the WebSocket connection by reading and writing messages.
     ///
-    /// It listens for incoming messages from the server and forwards them to the client,
-    /// and it also reads messages from the client.
-    pub async fn run(mut self) -> Result<(), AppError> {
-        // Accept the WebSocket connection
-        let ws_stream = accept_async(self.stream)
-            .await
-            .map_err(|e| AppError::WebSocketAcceptError(e.to_string()))?;
+    /// It forwards the server's requests to registered clients, stores the
+    /// reports they send back, and pushes price updates to consumers. Clients
+    /// are pinged periodically and dropped if they stay silent past the
+    /// heartbeat timeout. On shutdown, the client is sent a close frame and the
+    /// reports it still had in flight are recorded before the connection ends.
+    pub async fn run(self) -> Result<(), AppError> {
+        let WebSocketConnection {
+            stream,
+            receiver,
+            store,
+            history,
+            registry,
+            heartbeat,
+            shutdown,
+            verifier,
+            tls,
+        } = self;
+
+        let session = Session {
+            store,
+            history,
+            registry,
+            verifier,
+            role: Role::Pending,
+        };
+
+        // Peers stalling during a handshake are dropped like silent clients
+        match tls {
+            Some(acceptor) => {
+                let stream = timeout(heartbeat.timeout, acceptor.accept(stream))
+                    .await
+                    .map_err(|_| AppError::TlsError("TLS handshake timed out".to_string()))?
+                    .map_err(|e| AppError::TlsError(format!("TLS handshake failed: {}", e)))?;
+                serve(
+                    accept(stream, heartbeat.timeout).await?,
+                    receiver,
+                    session,
+                    heartbeat,
+                    shutdown,
+                )
+                .await
+            }
+            None => {
+                serve(
+                    accept(stream, heartbeat.timeout).await?,
+                    receiver,
+                    session,
+                    heartbeat,
+                    shutdown,
+                )
+                .await
+            }
+        }
+    }
+}
+
+/// Performs the WebSocket handshake over an accepted stream, giving up after `limit`.
+async fn accept<T>(stream: T, limit: Duration) -> Result<WebSocketStream<T>, AppError>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    timeout(limit, accept_async(stream))
+        .await
+        .map_err(|_| AppError::WebSocketAcceptError("WebSocket handshake timed out".to_string()))?
+        .map_err(|e| AppError::WebSocketAcceptError(e.to_string()))
+}
 
-        let (mut write, mut read) = ws_stream.split();
+/// Reads and writes the messages of an open WebSocket until it is closed.
+async fn serve<T>(
+    ws_stream: WebSocketStream<T>,
+    mut receiver: broadcast::Receiver<RequestTarget>,
+    mut session: Session,
+    heartbeat: Heartbeat,
+    shutdown: Shutdown,
+) -> Result<(), AppError>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    let (mut write, mut read) = ws_stream.split();
 
-        info!("New client connected.");
+    info!("New client connected.");
 
-        // Task for sending messages to the client
-        let mut send_task = tokio::spawn(async move {
-            while let Ok(msg) = self.receiver.recv().await {
-                if write.send(Message::Text(msg)).await.is_err() {
-                    return Err(AppError::WebSocketMessageError(
-                        "Error sending message to client".to_string(),
+    let mut ticker = heartbeat.ticker();
+    let mut last_seen = Instant::now();
+
+    let result = loop {
+        tokio::select! {
+            // Close the connection when the server shuts down
+            _ = shutdown.wait() => {
+                break close(&mut write, &mut read, &mut session).await;
+            },
+
+            // Ping the client, dropping it if it has gone silent
+            _ = ticker.tick() => {
+                if heartbeat.is_expired(last_seen) {
+                    warn!("Client missed its heartbeat for {:?}, dropping it", heartbeat.timeout);
+                    break Err(AppError::WebSocketMessageError(
+                        "Client heartbeat timed out".to_string(),
+                    ));
+                }
+                if write.send(Message::Ping(Vec::new())).await.is_err() {
+                    break Err(AppError::WebSocketMessageError(
+                        "Error sending ping to client".to_string(),
                     ));
                 }
+            },
+
+            // Forward the server requests addressed to this registered client
+            request = receiver.recv(), if matches!(session.role, Role::Reporter(..)) => match request {
+                Ok(target) => {
+                    let addressed = matches!(
+                        &session.role,
+                        Role::Reporter(registration, _) if target.matches(registration)
+                    );
+                    if addressed {
+                        if let Err(e) = send_message(&mut write, &ProtocolMessage::PriceRequest).await {
+                            break Err(e);
+                        }
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("Connection lagged behind, {} requests skipped", skipped)
+                }
+                Err(RecvError::Closed) => break Ok(()),
+            },
+
+            // Push new prices to consumers
+            update = next_update(&mut session.role) => match update {
+                Ok(record) => {
+                    let subscribed = matches!(
+                        &session.role,
+                        Role::Consumer { symbols, .. } if symbols.contains(&record.symbol)
+                    );
+                    if subscribed {
+                        let update = ProtocolMessage::PriceUpdate(record);
+                        if let Err(e) = send_message(&mut write, &update).await {
+                            break Err(e);
+                        }
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("Consumer lagged behind, {} updates skipped", skipped)
+                }
+                Err(RecvError::Closed) => break Ok(()),
+            },
+
+            msg = read.next() => {
+                // Any frame, pongs included, shows the client is alive
+                if let Some(Ok(_)) = &msg {
+                    last_seen = Instant::now();
+                }
+                match msg {
+                    Some(Ok(Message::Text(text))) => {
+                        debug!("Message received from client: {}", text);
+                        let reply = match protocol::decode(&text) {
+                            Ok(message) => session.handle_message(message).await,
+                            Err(e) => {
+                                warn!("Invalid message from client: {}", e);
+                                Some(ProtocolMessage::error(protocol::error_code(&e), e.to_string()))
+                            }
+                        };
+                        if let Some(reply) = reply {
+                            if let Err(e) = send_message(&mut write, &reply).await {
+                                break Err(e);
+                            }
+                        }
+                    }
+                    Some(Ok(Message::Close(_))) | None => break Ok(()),
+                    Some(Ok(_)) => {}
+                    Some(Err(e)) => {
+                        break Err(AppError::WebSocketMessageError(format!(
+                            "Error reading message from client: {}",
+                            e
+                        )))
+                    }
+                }
+            },
+        }
+    };
+
+    if let Role::Reporter(registration, connection) = &session.role {
+        session
+            .registry
+            .unregister(&registration.token, &registration.client_id, *connection);
+        info!(
+            "Client {} unregistered for token {}",
+            registration.client_id, registration.token
+        );
+    }
+
+    info!("Client disconnected.");
+    result
+}
+
+impl Session {
+    /// Handles a decoded message from the peer and returns the reply to send back, if any.
+    async fn handle_message(&mut self, message: ProtocolMessage) -> Option<ProtocolMessage> {
+        match (&self.role, message) {
+            (Role::Pending, ProtocolMessage::Register(registration)) => {
+                // Only allowlisted clients may claim a token when reports must be signed
+                if let Some(verifier) = &self.verifier {
+                    if let Err(e) = verifier.verify_registration(&registration) {
+                        warn!(
+                            "Rejected registration of client {} for token {}: {}",
+                            registration.client_id, registration.token, e
+                        );
+                        return Some(ProtocolMessage::error(ErrorCode::Rejected, e.to_string()));
+                    }
+                }
+                match self.registry.register(registration.clone()) {
+                    Ok(connection) => {
+                        info!(
+                            "Client {} (v{}) registered for token {}",
+                            registration.client_id, registration.client_version, registration.token
+                        );
+                        self.role = Role::Reporter(registration, connection);
+                        Some(ProtocolMessage::Ack {
+                            of: "register".to_string(),
+                        })
+                    }
+                    Err(e) => {
+                        warn!("{}", e);
+                        Some(ProtocolMessage::error(ErrorCode::Rejected, e.to_string()))
+                    }
+                }
             }
-            Ok::<(), AppError>(())
-        });
-
-        // Task for receiving messages from the client
-        let mut receive_task = tokio::spawn(async move {
-            while let Some(Ok(msg)) = read.next().await {
-                if let Message::Text(text) = msg {
-                    info!("Message received from client: {}", text);
+            (Role::Pending | Role::Consumer { .. }, ProtocolMessage::Subscribe { symbols }) => {
+                let symbols: HashSet<String> = symbols.iter().map(|s| s.to_uppercase()).collect();
+                info!("Consumer subscribed to {:?}", symbols);
+
+                // Subscribe before taking the snapshot so no update is missed
+                let updates = self.store.subscribe();
+                let prices = self
+                    .store
+                    .all()
+                    .into_iter()
+                    .filter(|r| symbols.contains(&r.symbol))
+                    .collect();
+                let unpublished = self
+                    .store
+                    .all_unpublished()
+                    .into_iter()
+                    .filter(|u| symbols.contains(&u.symbol))
+                    .collect();
+
+                self.role = Role::Consumer { symbols, updates };
+                Some(ProtocolMessage::Snapshot {
+                    prices,
+                    unpublished,
+                })
+            }
+            (Role::Reporter(registration, _), ProtocolMessage::PriceReport(report)) => {
+                if report.contract_address != registration.contract_address {
+                    warn!(
+                        "Rejected report for {} from client {} registered for {}",
+                        report.contract_address, registration.client_id, registration.token
+                    );
+                    return Some(ProtocolMessage::error(
+                        ErrorCode::Rejected,
+                        format!("Client is not registered for {}", report.contract_address),
+                    ));
+                }
+                if let Some(verifier) = &self.verifier {
+                    if let Err(e) = verifier.verify(&report) {
+                        warn!(
+                            "Rejected report from client {}: {}",
+                            registration.client_id, e
+                        );
+                        return Some(ProtocolMessage::error(ErrorCode::Rejected, e.to_string()));
+                    }
                 }
+                // Publish under the registered token, whatever symbol the source used
+                let mut report = report;
+                report.quote.symbol = registration.token.clone();
+                Some(handle_report(&self.store, self.history.as_ref(), report).await)
+            }
+            (Role::Pending, ProtocolMessage::PriceReport(_)) => Some(ProtocolMessage::error(
+                ErrorCode::Rejected,
+                "Client must register before reporting prices",
+            )),
+            (_, ProtocolMessage::Error { code, message }) => {
+                warn!("Client reported an error ({:?}): {}", code, message);
+                None
+            }
+            (_, ProtocolMessage::Ack { .. }) => None,
+            (_, other) => {
+                warn!("Unexpected {} message from client", other.kind());
+                Some(ProtocolMessage::error(
+                    ErrorCode::UnexpectedMessage,
+                    format!("Unexpected {} message", other.kind()),
+                ))
             }
-            Ok::<(), AppError>(())
-        });
+        }
+    }
+}
 
-        // Await the completion of both tasks
-        tokio::select! {
-            result = &mut send_task => result.map_err(|_| AppError::UnknownError("Error in send task".to_string()))??,
-            result = &mut receive_task => result.map_err(|_| AppError::UnknownError("Error in receive task".to_string()))??,
+/// Records a price report from a client and returns the reply to send back once it
+/// is stored, so no accepted report is lost when the server shuts down.
+async fn handle_report(
+    store: &PriceStore,
+    history: Option<&PriceHistory>,
+    report: PriceReport,
+) -> ProtocolMessage {
+    info!("Price report received from client: {:?}", report);
+
+    // Keep flagged reports out of the published price
+    let flags = store.check(&report);
+    if !flags.is_empty() {
+        let reasons: Vec<&str> = flags.iter().map(|f| f.message.as_str()).collect();
+        warn!(
+            "Excluded report for {}: {}",
+            report.quote.symbol,
+            reasons.join("; ")
+        );
+        let message = format!(
+            "Report excluded from the published price: {}",
+            reasons.join("; ")
+        );
+        if store.exclude(report, flags).is_none() {
+            debug!("No published price to flag, kept as unpublished");
+        }
+        return ProtocolMessage::error(ErrorCode::Rejected, message);
+    }
+
+    // Reports held back by the publishing policy still belong to the history
+    let received_at = Utc::now();
+    let (record, published) = match store.update(report.clone(), received_at) {
+        Some(record) => {
+            debug!("Price stored: {:?}", record);
+            (record, true)
+        }
+        None => {
+            debug!("Report not published: out of date, or held back by the publishing policy");
+            (PriceRecord::from_report(report, received_at), false)
+        }
+    };
+    // SQLite calls block, keep them off the async workers
+    if let Some(history) = history.cloned() {
+        let appended = tokio::task::spawn_blocking(move || history.append(&record, published))
+            .await
+            .unwrap_or_else(|e| Err(AppError::DatabaseError(e.to_string())));
+        if let Err(e) = appended {
+            error!("{}", e);
+        }
+    }
+    ProtocolMessage::Ack {
+        of: "price_report".to_string(),
+    }
+}
+
+/// Sends a close frame to the client and records the messages it sends until it
+/// acknowledges the close, or until [`CLOSE_TIMEOUT`] elapses.
+async fn close<W, R>(write: &mut W, read: &mut R, session: &mut Session) -> Result<(), AppError>
+where
+    W: SinkExt<Message> + Unpin,
+    R: Stream<Item = Result<Message, tokio_tungstenite::tungstenite::Error>> + Unpin,
+{
+    info!("Server shutting down, closing connection.");
+    let frame = CloseFrame {
+        code: CloseCode::Away,
+        reason: "server shutting down".into(),
+    };
+    write
+        .send(Message::Close(Some(frame)))
+        .await
+        .map_err(|_| AppError::WebSocketMessageError("Error sending close frame".to_string()))?;
+
+    let drain = async {
+        while let Some(Ok(message)) = read.next().await {
+            match message {
+                // Replies cannot be sent once closing, only the report itself matters
+                Message::Text(text) => match protocol::decode(&text) {
+                    Ok(message) => {
+                        session.handle_message(message).await;
+                    }
+                    Err(e) => warn!("Invalid message from closing client: {}", e),
+                },
+                Message::Close(_) => break,
+                _ => {}
+            }
         }
+    };
+    if timeout(CLOSE_TIMEOUT, drain).await.is_err() {
+        warn!("Client did not acknowledge the close frame in time");
+    }
+    Ok(())
+}
 
-        info!("Client disconnected.");
-        Ok(())
+/// Waits for the next update of a consumer, or forever if the connection is not one.
+async fn next_update(role: &mut Role) -> Result<PriceRecord, RecvError> {
+    match role {
+        Role::Consumer { updates, .. } => updates.recv().await,
+        _ => std::future::pending().await,
     }
 }
+
+/// Encodes a protocol message and sends it to the client.
+async fn send_message<S>(write: &mut S, message: &ProtocolMessage) -> Result<(), AppError>
+where
+    S: SinkExt<Message> + Unpin,
+{
+    let text = protocol::encode(message)?;
+    write
+        .send(Message::Text(text))
+        .await
+        .map_err(|_| AppError::WebSocketMessageError("Error sending message to client".to_string()))
+}
@@ -1,114 +1,415 @@
+use futures_util::future::BoxFuture;
 use futures_util::{SinkExt, StreamExt};
-use log::{debug, error, info};
-use std::env;
+use log::{debug, error, info, warn};
+use std::fmt;
+use std::sync::Arc;
 use tokio::sync::broadcast;
-use tokio_tungstenite::connect_async;
-use tokio_tungstenite::tungstenite::protocol::Message;
+use tokio::time::{sleep, timeout, Duration, Instant};
+use tokio_rustls::rustls::ClientConfig;
+use tokio_tungstenite::tungstenite::protocol::{frame::coding::CloseCode, CloseFrame, Message};
+use tokio_tungstenite::{connect_async_tls_with_config, Connector};
 
-use crate::{infraestructure::api_client::ApiClient, AppError};
+use super::backoff::Backoff;
+use super::client_registry::ClientRegistration;
+use super::heartbeat::Heartbeat;
+use super::price_provider::{PriceProvider, PriceQuote, TokenRef};
+use super::price_store::PriceReport;
+use super::protocol::{self, ErrorCode, ProtocolMessage};
+use super::shutdown::Shutdown;
+use super::signing::ReportSigner;
+use super::websocket_server::DEFAULT_SERVER_HOST;
+use crate::AppError;
+
+/// How long a closing client waits for the server to acknowledge its close frame.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// State of the connection between a client and the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// Opening the WebSocket connection.
+    Connecting,
+    /// Connected, waiting for the server to accept the registration.
+    Connected,
+    /// Registered and serving price requests.
+    Registered,
+    /// The connection was closed or could not be opened.
+    Disconnected { reason: String },
+    /// Waiting before the given reconnection attempt.
+    Reconnecting { attempt: u32, delay: Duration },
+    /// Gave up after an error that reconnecting cannot fix.
+    Stopped { reason: String },
+    /// Closed because the client is shutting down.
+    ShutDown,
+}
+
+/// Callback notified of every connection state transition.
+pub type StateCallback = Arc<dyn Fn(&ConnectionState) + Send + Sync>;
 
 // WebSocket handler for managing WebSocket connections and messages.
-#[derive(Debug)]
 pub struct WebSocketHandler {
-    token_name: String,
-    contract_address: String,
+    token: TokenRef,
+    provider: Arc<dyn PriceProvider>,
+    client_id: String,
+    server_host: String,
+    backoff: Backoff,
+    heartbeat: Heartbeat,
+    shutdown: Shutdown,
+    signer: Option<Arc<ReportSigner>>,
+    tls: Option<Arc<ClientConfig>>,
+    on_state: Option<StateCallback>,
     #[allow(dead_code)]
     tx: broadcast::Sender<(String, String)>,
 }
 
+impl fmt::Debug for WebSocketHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebSocketHandler")
+            .field("token", &self.token)
+            .field("provider", &self.provider)
+            .field("client_id", &self.client_id)
+            .field("server_host", &self.server_host)
+            .field("backoff", &self.backoff)
+            .field("heartbeat", &self.heartbeat)
+            .field("signer", &self.signer)
+            .field("tls", &self.tls.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
 impl WebSocketHandler {
     /// Creates a new WebSocketHandler instance.
+    ///
+    /// Prices are fetched from `provider` whenever the server requests them.
     pub fn new(
-        token_name: String,
-        contract_address: String,
+        token: TokenRef,
+        provider: Arc<dyn PriceProvider>,
         tx: broadcast::Sender<(String, String)>,
     ) -> Self {
+        let client_id = format!("{}-{}", token.name.to_lowercase(), std::process::id());
         WebSocketHandler {
-            token_name,
-            contract_address,
+            token,
+            provider,
+            client_id,
+            server_host: DEFAULT_SERVER_HOST.to_string(),
+            backoff: Backoff::default(),
+            heartbeat: Heartbeat::default(),
+            shutdown: Shutdown::new(),
+            signer: None,
+            tls: None,
+            on_state: None,
             tx,
         }
     }
 
-    /// Connects to the WebSocket server and handles incoming messages.
-    pub async fn connect(&self) -> Result<(), AppError> {
-        // Read the SERVER_HOST environment variable
-        let server_host = match env::var("SERVER_HOST") {
-            Ok(host) => host,
-            Err(_) => {
-                return Err(AppError::FileError(
-                    "SERVER_HOST not found in .env file".to_string(),
-                ));
+    /// Connects to the given server instead of [`DEFAULT_SERVER_HOST`].
+    pub fn with_server_host(mut self, host: &str) -> Self {
+        self.server_host = host.to_string();
+        self
+    }
+
+    /// Sets the backoff policy used between reconnection attempts.
+    pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Sets the ping interval and the timeout after which a silent server is
+    /// considered gone and the client reconnects.
+    pub fn with_heartbeat(mut self, heartbeat: Heartbeat) -> Self {
+        self.heartbeat = heartbeat;
+        self
+    }
+
+    /// Closes the connection and stops reconnecting once `shutdown` is triggered.
+    pub fn with_shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    /// Signs every price report with the given key.
+    pub fn with_signer(mut self, signer: Arc<ReportSigner>) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    /// Connects with `wss://` using the given TLS configuration.
+    pub fn with_tls(mut self, config: Arc<ClientConfig>) -> Self {
+        self.tls = Some(config);
+        self
+    }
+
+    /// Registers a callback notified of every connection state transition.
+    pub fn with_state_callback(
+        mut self,
+        callback: impl Fn(&ConnectionState) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_state = Some(Arc::new(callback));
+        self
+    }
+
+    /// Logs a state transition and notifies the callback, if any.
+    fn set_state(&self, state: ConnectionState) {
+        match &state {
+            ConnectionState::Disconnected { reason } => {
+                warn!("Client {} disconnected: {}", self.client_id, reason)
+            }
+            ConnectionState::Reconnecting { attempt, delay } => info!(
+                "Client {} reconnecting in {:?} (attempt {})",
+                self.client_id, delay, attempt
+            ),
+            ConnectionState::Stopped { reason } => {
+                error!("Client {} stopped: {}", self.client_id, reason)
+            }
+            ConnectionState::ShutDown => info!("Client {} shut down", self.client_id),
+            other => debug!("Client {} is {:?}", self.client_id, other),
+        }
+        if let Some(callback) = &self.on_state {
+            callback(&state);
+        }
+    }
+
+    /// Keeps the client connected to the server, reconnecting with exponential backoff
+    /// and registering again whenever the connection is lost.
+    ///
+    /// Returns `Ok` once shutdown is triggered, or an error that cannot be fixed by
+    /// reconnecting, such as a server refusing the protocol of the client. Rejected
+    /// registrations are retried like lost connections.
+    pub async fn run(&self) -> Result<(), AppError> {
+        let mut attempt = 0;
+        loop {
+            let mut registered = false;
+            let result = self.session(&mut registered).await;
+            if registered {
+                attempt = 0;
+            }
+
+            if self.shutdown.is_triggered() {
+                self.set_state(ConnectionState::ShutDown);
+                return Ok(());
             }
-        };
-        let server_url = format!("ws://{}", server_host);
+
+            let reason = match result {
+                Ok(()) => "connection closed by the server".to_string(),
+                Err(e @ AppError::ProtocolError(_)) => {
+                    self.set_state(ConnectionState::Stopped {
+                        reason: e.to_string(),
+                    });
+                    return Err(e);
+                }
+                Err(e) => e.to_string(),
+            };
+            self.set_state(ConnectionState::Disconnected { reason });
+
+            attempt += 1;
+            let delay = self.backoff.delay(attempt);
+            self.set_state(ConnectionState::Reconnecting { attempt, delay });
+            tokio::select! {
+                _ = sleep(delay) => {}
+                _ = self.shutdown.wait() => {
+                    self.set_state(ConnectionState::ShutDown);
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Returns the registration sent to the server on connect, signed if the client
+    /// signs its reports.
+    fn registration(&self) -> ClientRegistration {
+        let registration = ClientRegistration::new(
+            self.token.name.clone(),
+            self.token.contract_address.clone(),
+            self.client_id.clone(),
+            env!("CARGO_PKG_VERSION").to_string(),
+        );
+        match &self.signer {
+            Some(signer) => signer.sign_registration(registration),
+            None => registration,
+        }
+    }
+
+    /// Connects to the WebSocket server and handles incoming messages until the
+    /// connection is closed.
+    pub async fn connect(&self) -> Result<(), AppError> {
+        self.session(&mut false).await
+    }
+
+    /// Runs a single connection, setting `registered` once the server accepts the client.
+    async fn session(&self, registered: &mut bool) -> Result<(), AppError> {
+        let scheme = if self.tls.is_some() { "wss" } else { "ws" };
+        let server_url = format!("{}://{}", scheme, self.server_host);
+        let connector = self.tls.clone().map(Connector::Rustls);
+        self.set_state(ConnectionState::Connecting);
 
         // Attempt to connect to the WebSocket server
-        let (ws_stream, _) = connect_async(&server_url).await.map_err(|e| {
-            AppError::TcpError(format!("Error connecting to {}: {}", &server_url, e))
-        })?;
+        let (ws_stream, _) = connect_async_tls_with_config(&server_url, None, false, connector)
+            .await
+            .map_err(|e| {
+                AppError::TcpError(format!("Error connecting to {}: {}", &server_url, e))
+            })?;
 
         let (mut write, mut read) = ws_stream.split();
-        info!("Client connected with Token: {}", self.token_name);
-
-        while let Some(Ok(msg)) = read.next().await {
-            if let Message::Text(text) = msg {
-                // Check if the message is a request for token price
-                if text == "REQUEST_TOKEN_PRICE" {
-                    match ApiClient::new(self.contract_address.clone())
-                        .fetch_price()
-                        .await
-                    {
-                        Ok(api_response) => {
-                            match ApiClient::process_api_response(&api_response) {
-                                Ok(processed_response) => {
-                                    // Send the processed response back to the WebSocket
-                                    write
-                                        .send(Message::Text(processed_response))
-                                        .await
-                                        .map_err(|e| {
-                                            AppError::WebSocketMessageError(format!(
-                                                "Error sending message: {}",
-                                                e
-                                            ))
-                                        })?;
-                                    debug!("Message sent successfully");
-                                }
-                                Err(e) => {
-                                    // Handle API response processing error
-                                    write
-                                        .send(Message::Text(format!(
-                                            "Error processing response: {}",
-                                            e
-                                        )))
-                                        .await
-                                        .map_err(|e| {
-                                            AppError::WebSocketMessageError(format!(
-                                                "Error sending error message: {}",
-                                                e
-                                            ))
-                                        })?;
-                                    error!("Error processing API response: {}", e);
-                                }
+        info!("Client connected with Token: {}", self.token.name);
+        self.set_state(ConnectionState::Connected);
+
+        // Register the token served by this client
+        let register = ProtocolMessage::Register(self.registration());
+        write
+            .send(Message::Text(protocol::encode(&register)?))
+            .await
+            .map_err(|e| {
+                AppError::WebSocketMessageError(format!("Error sending registration: {}", e))
+            })?;
+
+        let mut ticker = self.heartbeat.ticker();
+        let mut last_seen = Instant::now();
+        // Price fetch in flight, raced against shutdown, heartbeats and server messages
+        let mut fetch: Option<BoxFuture<'_, Result<PriceQuote, AppError>>> = None;
+
+        loop {
+            let msg = tokio::select! {
+                // Close the connection when the client shuts down
+                _ = self.shutdown.wait() => {
+                    let frame = CloseFrame {
+                        code: CloseCode::Away,
+                        reason: "client shutting down".into(),
+                    };
+                    write.send(Message::Close(Some(frame))).await.map_err(|e| {
+                        AppError::WebSocketMessageError(format!("Error sending close frame: {}", e))
+                    })?;
+                    // Wait for the server to acknowledge the close
+                    let acknowledged = async {
+                        while let Some(Ok(msg)) = read.next().await {
+                            if let Message::Close(_) = msg {
+                                break;
                             }
                         }
-                        Err(e) => {
-                            // Handle API call error
-                            write
-                                .send(Message::Text(format!("Error calling API: {}", e)))
-                                .await
-                                .map_err(|e| {
-                                    AppError::WebSocketMessageError(format!(
-                                        "Error sending API error: {}",
-                                        e
-                                    ))
-                                })?;
-                            error!("Error calling the API: {}", e);
-                        }
+                    };
+                    if timeout(CLOSE_TIMEOUT, acknowledged).await.is_err() {
+                        warn!("Server did not acknowledge the close frame in time");
                     }
+                    return Ok(());
                 }
-            }
+                // Ping the server, giving up on the connection if it has gone silent
+                _ = ticker.tick() => {
+                    if self.heartbeat.is_expired(last_seen) {
+                        return Err(AppError::WebSocketMessageError(format!(
+                            "No heartbeat from the server for {:?}",
+                            self.heartbeat.timeout
+                        )));
+                    }
+                    write.send(Message::Ping(Vec::new())).await.map_err(|e| {
+                        AppError::WebSocketMessageError(format!("Error sending ping: {}", e))
+                    })?;
+                    continue;
+                }
+                // Report the fetched price once the provider answers
+                result = async { fetch.as_mut().expect("fetch in flight").await }, if fetch.is_some() => {
+                    fetch = None;
+                    let reply = self.price_report(result);
+                    write
+                        .send(Message::Text(protocol::encode(&reply)?))
+                        .await
+                        .map_err(|e| {
+                            AppError::WebSocketMessageError(format!("Error sending message: {}", e))
+                        })?;
+                    debug!("Message sent successfully");
+                    continue;
+                }
+                msg = read.next() => match msg {
+                    Some(Ok(msg)) => msg,
+                    Some(Err(e)) => {
+                        return Err(AppError::WebSocketMessageError(format!(
+                            "Error reading message from server: {}",
+                            e
+                        )))
+                    }
+                    None => break,
+                },
+            };
+
+            // Any frame, pongs included, shows the server is alive
+            last_seen = Instant::now();
+            let text = match msg {
+                Message::Text(text) => text,
+                Message::Close(_) => break,
+                _ => continue,
+            };
+
+            let reply = match protocol::decode(&text) {
+                Ok(ProtocolMessage::PriceRequest) => {
+                    if fetch.is_some() {
+                        debug!("Price of {} is already being fetched", self.token.name);
+                    } else {
+                        fetch = Some(self.provider.fetch(&self.token));
+                    }
+                    continue;
+                }
+                Ok(ProtocolMessage::Ack { of }) => {
+                    if of == "register" {
+                        *registered = true;
+                        info!("Client registered with Token: {}", self.token.name);
+                        self.set_state(ConnectionState::Registered);
+                    }
+                    debug!("Server acknowledged {}", of);
+                    continue;
+                }
+                // Another client may hold the token for now, so a rejection is retried
+                Ok(ProtocolMessage::Error {
+                    code: ErrorCode::Rejected,
+                    message,
+                }) if !*registered => {
+                    return Err(AppError::RegistrationError(format!(
+                        "Server rejected registration: {}",
+                        message
+                    )));
+                }
+                Ok(ProtocolMessage::Error { code, message }) if !*registered => {
+                    return Err(AppError::ProtocolError(format!(
+                        "Server refused the connection ({:?}): {}",
+                        code, message
+                    )));
+                }
+                Ok(ProtocolMessage::Error { code, message }) => {
+                    error!("Server reported an error ({:?}): {}", code, message);
+                    continue;
+                }
+                Ok(other) => {
+                    warn!("Unexpected {} message from server", other.kind());
+                    continue;
+                }
+                Err(e) => {
+                    error!("Invalid message from server: {}", e);
+                    ProtocolMessage::error(protocol::error_code(&e), e.to_string())
+                }
+            };
+
+            // Send the reply back to the WebSocket
+            write
+                .send(Message::Text(protocol::encode(&reply)?))
+                .await
+                .map_err(|e| {
+                    AppError::WebSocketMessageError(format!("Error sending message: {}", e))
+                })?;
+            debug!("Message sent successfully");
         }
         Ok(())
     }
+
+    /// Returns the reply to a price request once the provider answered.
+    fn price_report(&self, result: Result<PriceQuote, AppError>) -> ProtocolMessage {
+        match result {
+            Ok(quote) => {
+                let report = PriceReport::new(self.token.contract_address.clone(), quote);
+                ProtocolMessage::PriceReport(match &self.signer {
+                    Some(signer) => signer.sign(report),
+                    None => report,
+                })
+            }
+            Err(e) => {
+                error!("Error fetching price from {}: {}", self.provider.name(), e);
+                ProtocolMessage::error(ErrorCode::UpstreamError, e.to_string())
+            }
+        }
+    }
 }
@@ -1,15 +1,44 @@
 // websocket_server.rs
+use super::client_registry::ClientRegistry;
+use super::heartbeat::Heartbeat;
+use super::price_requester::PriceRequester;
+use super::price_store::PriceStore;
+use super::scheduler::{Schedule, Scheduler};
+use super::shutdown::Shutdown;
+use super::signing::ReportVerifier;
 use super::websocket_connection::WebSocketConnection;
-use crate::AppError;
-use log::{error, info, warn};
+use crate::{infraestructure::price_history::PriceHistory, AppError};
+use log::{error, info};
+use std::collections::HashMap;
+use std::sync::Arc;
 use tokio::net::TcpListener;
 use tokio::sync::broadcast;
+use tokio::task::JoinSet;
 use tokio::time::{sleep, Duration};
+use tokio_rustls::{rustls::ServerConfig, TlsAcceptor};
 
-/// A WebSocket server that listens for incoming WebSocket connections and broadcasts messages to clients.
+/// How often records outside the history retention window are deleted.
+const HISTORY_PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Default address of the WebSocket server.
+pub const DEFAULT_SERVER_HOST: &str = "127.0.0.1:8080";
+
+/// Default time between two price requests to the same token.
+pub const DEFAULT_REQUEST_INTERVAL: Duration = Duration::from_secs(10);
+
+/// A WebSocket server that listens for incoming WebSocket connections and requests prices from clients.
 pub struct WebSocketServer {
     address: String,
-    broadcaster: broadcast::Sender<String>,
+    requester: PriceRequester,
+    default_schedule: Schedule,
+    schedules: HashMap<String, Schedule>,
+    store: PriceStore,
+    history: Option<PriceHistory>,
+    registry: ClientRegistry,
+    heartbeat: Heartbeat,
+    shutdown: Shutdown,
+    verifier: Option<ReportVerifier>,
+    tls: Option<TlsAcceptor>,
 }
 
 impl WebSocketServer {
@@ -22,52 +51,181 @@ impl WebSocketServer {
     /// * `Ok(Self)` if the server was successfully created.
     /// * `Err(AppError)` if an error occurred during initialization.
     pub fn new(address: &str) -> Result<Self, AppError> {
+        Self::from_store(address, PriceStore::new())
+    }
+
+    /// Creates a new WebSocket server publishing the prices into the given store.
+    ///
+    /// The store attests and filters the prices as it was built to, see
+    /// [`PriceStore::with_attestor`] and its policies.
+    pub fn from_store(address: &str, store: PriceStore) -> Result<Self, AppError> {
         let (tx, _) = broadcast::channel(16);
+        let registry = ClientRegistry::new();
         Ok(Self {
             address: address.to_string(),
-            broadcaster: tx,
+            requester: PriceRequester::new(tx, registry.clone()),
+            default_schedule: Schedule::new(DEFAULT_REQUEST_INTERVAL, Duration::ZERO),
+            schedules: HashMap::new(),
+            store,
+            history: None,
+            registry,
+            heartbeat: Heartbeat::default(),
+            shutdown: Shutdown::new(),
+            verifier: None,
+            tls: None,
         })
     }
 
+    /// Sets the polling schedule of the tokens without their own schedule.
+    pub fn with_default_schedule(mut self, schedule: Schedule) -> Self {
+        self.default_schedule = schedule;
+        self
+    }
+
+    /// Sets the polling schedule of a specific token.
+    pub fn with_token_schedule(mut self, token: &str, schedule: Schedule) -> Self {
+        self.schedules.insert(token.to_uppercase(), schedule);
+        self
+    }
+
+    /// Sets the ping interval and the timeout after which silent clients are dropped.
+    pub fn with_heartbeat(mut self, heartbeat: Heartbeat) -> Self {
+        self.heartbeat = heartbeat;
+        self
+    }
+
+    /// Stops the server once `shutdown` is triggered.
+    pub fn with_shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    /// Only accepts price reports signed by a key of the verifier's allowlist.
+    pub fn with_report_verifier(mut self, verifier: ReportVerifier) -> Self {
+        self.verifier = Some(verifier);
+        self
+    }
+
+    /// Serves `wss://` connections using the given TLS configuration.
+    pub fn with_tls(mut self, config: Arc<ServerConfig>) -> Self {
+        self.tls = Some(TlsAcceptor::from(config));
+        self
+    }
+
+    /// Returns a handle to address price requests to specific tokens or clients.
+    pub fn requester(&self) -> PriceRequester {
+        self.requester.clone()
+    }
+
+    /// Returns a handle to the registry of connected clients.
+    pub fn registry(&self) -> ClientRegistry {
+        self.registry.clone()
+    }
+
+    /// Persists every accepted price report to the given history.
+    pub fn with_history(mut self, history: PriceHistory) -> Self {
+        self.history = Some(history);
+        self
+    }
+
+    /// Returns a handle to the store holding the latest price per symbol.
+    pub fn store(&self) -> PriceStore {
+        self.store.clone()
+    }
+
     /// Starts the WebSocket server and listens for incoming connections.
     ///
-    /// It handles client connections asynchronously and periodically broadcasts messages to all clients.
+    /// It handles client connections asynchronously and requests the price of every
+    /// registered token according to that token's polling schedule.
+    ///
+    /// Once shutdown is triggered, it stops accepting connections, closes the
+    /// open ones and returns after all of them have finished.
     pub async fn run(&self) -> Result<(), AppError> {
         // Bind the server to the specified address
         let listener = TcpListener::bind(&self.address)
             .await
             .map_err(|e| AppError::TcpError(e.to_string()))?;
 
-        info!("Server listening on {}", &self.address);
+        let scheme = if self.tls.is_some() { "wss" } else { "ws" };
+        info!("Server listening on {}://{}", scheme, &self.address);
 
-        let broadcaster = self.broadcaster.clone();
+        // Request the price of every registered token on its own schedule
+        let scheduler = Scheduler::new(
+            self.requester.clone(),
+            self.registry.clone(),
+            self.default_schedule,
+            self.schedules.clone(),
+        );
+        let mut background = vec![tokio::spawn(scheduler.run())];
 
-        // Periodically send a "REQUEST_TOKEN_PRICE" message to all clients
-        tokio::spawn(async move {
-            loop {
-                if broadcaster.send("REQUEST_TOKEN_PRICE".to_string()).is_err() {
-                    warn!(
-                        "{}",
-                        AppError::BroadcastError("No clients listening".to_string())
-                    );
+        // Periodically drop history records older than the retention window
+        if let Some(history) = self.history.clone() {
+            background.push(tokio::spawn(async move {
+                loop {
+                    let history = history.clone();
+                    let pruned = tokio::task::spawn_blocking(move || history.prune())
+                        .await
+                        .unwrap_or_else(|e| Err(AppError::DatabaseError(e.to_string())));
+                    if let Err(e) = pruned {
+                        error!("{}", e);
+                    }
+                    sleep(HISTORY_PRUNE_INTERVAL).await;
                 }
-                sleep(Duration::from_secs(10)).await;
-            }
-        });
+            }));
+        }
+
+        // Accept incoming connections until shutdown
+        let mut connections = JoinSet::new();
+        let result = loop {
+            let stream = tokio::select! {
+                _ = self.shutdown.wait() => break Ok(()),
+                accepted = listener.accept() => match accepted {
+                    Ok((stream, _)) => stream,
+                    Err(e) => {
+                        // Close the open connections too, the server cannot go on
+                        self.shutdown.trigger();
+                        break Err(AppError::TcpError(e.to_string()));
+                    }
+                },
+            };
+
+            // Forget the connections that already ended
+            while connections.try_join_next().is_some() {}
 
-        // Accept incoming connections
-        while let Ok((stream, _)) = listener.accept().await {
-            let tx = self.broadcaster.clone();
-            let rx = tx.subscribe();
+            let rx = self.requester.subscribe();
+            let store = self.store.clone();
+            let history = self.history.clone();
+            let registry = self.registry.clone();
+            let heartbeat = self.heartbeat;
+            let shutdown = self.shutdown.clone();
+            let verifier = self.verifier.clone();
+            let tls = self.tls.clone();
 
             // Spawn a new task to handle the WebSocket connection
-            tokio::spawn(async move {
-                if let Err(e) = WebSocketConnection::new(stream, rx).run().await {
+            connections.spawn(async move {
+                let mut connection = WebSocketConnection::new(stream, rx, store, history, registry)
+                    .with_heartbeat(heartbeat)
+                    .with_shutdown(shutdown);
+                if let Some(verifier) = verifier {
+                    connection = connection.with_report_verifier(verifier);
+                }
+                if let Some(acceptor) = tls {
+                    connection = connection.with_tls(acceptor);
+                }
+                if let Err(e) = connection.run().await {
                     error!("Error in connection: {}", e);
                 }
             });
+        };
+
+        for task in background {
+            task.abort();
         }
 
-        Ok(())
+        info!("Waiting for {} connections to close", connections.len());
+        while connections.join_next().await.is_some() {}
+        info!("Server stopped");
+
+        result
     }
 }
@@ -0,0 +1,99 @@
+use chrono::{DateTime, Duration, Utc};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::AppError;
+
+/// Default time a resolved contract address is trusted before being looked up again.
+pub const DEFAULT_ADDRESS_CACHE_TTL: Duration = Duration::days(7);
+
+/// A contract address resolved from a token symbol.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CachedAddress {
+    pub coin_type: String,
+    pub resolved_at: DateTime<Utc>,
+}
+
+// On-disk cache of the contract addresses resolved on CoinGecko, keyed by token symbol.
+#[derive(Debug, Clone)]
+pub struct AddressCache {
+    path: PathBuf,
+    ttl: Duration,
+    entries: HashMap<String, CachedAddress>,
+}
+
+impl AddressCache {
+    /// Opens the cache stored at the given path.
+    ///
+    /// A missing file gives an empty cache, and so does an unreadable one, which is
+    /// overwritten on the next [`Self::save`].
+    ///
+    /// # Arguments
+    /// * `path` - Path of the JSON cache file.
+    /// * `ttl` - How long a resolved address is fresh.
+    pub fn open(path: impl AsRef<Path>, ttl: Duration) -> Self {
+        let path = path.as_ref().to_path_buf();
+        let entries = match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                warn!(
+                    "Ignoring unreadable address cache {}: {}",
+                    path.display(),
+                    e
+                );
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+        AddressCache { path, ttl, entries }
+    }
+
+    /// Returns the coin type of a token if it was resolved less than the TTL ago.
+    pub fn get(&self, symbol: &str) -> Option<&str> {
+        self.entries
+            .get(&symbol.to_uppercase())
+            .filter(|entry| Utc::now() - entry.resolved_at < self.ttl)
+            .map(|entry| entry.coin_type.as_str())
+    }
+
+    /// Returns the coin type of a token however long ago it was resolved.
+    pub fn get_stale(&self, symbol: &str) -> Option<&CachedAddress> {
+        self.entries.get(&symbol.to_uppercase())
+    }
+
+    /// Records the coin type just resolved for a token.
+    pub fn insert(&mut self, symbol: &str, coin_type: &str) {
+        self.entries.insert(
+            symbol.to_uppercase(),
+            CachedAddress {
+                coin_type: coin_type.to_string(),
+                resolved_at: Utc::now(),
+            },
+        );
+    }
+
+    /// Writes the cache to its file, replacing the previous one atomically.
+    pub fn save(&self) -> Result<(), AppError> {
+        let contents = serde_json::to_string_pretty(&self.entries)
+            .map_err(|e| AppError::JsonError(format!("Error serializing address cache: {}", e)))?;
+
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, contents)
+            .and_then(|_| fs::rename(&tmp_path, &self.path))
+            .map_err(|e| {
+                AppError::FileError(format!(
+                    "Error writing address cache {}: {}",
+                    self.path.display(),
+                    e
+                ))
+            })?;
+        debug!(
+            "Saved {} addresses to {}",
+            self.entries.len(),
+            self.path.display()
+        );
+        Ok(())
+    }
+}
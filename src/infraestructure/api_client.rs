@@ -1,27 +1,85 @@
+use async_trait::async_trait;
 use chrono::{TimeZone, Utc};
 use log::debug;
-use serde_json::Value;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::time::Duration;
 
-use crate::AppError;
+use crate::{
+    domain::price_provider::{PriceProvider, PriceQuote, TokenRef},
+    AppError,
+};
 
-const API_FETCH_PRICE: &str = "https://coins.llama.fi/prices/current/sui";
+/// Base URL of the public DefiLlama coins API.
+pub const DEFAULT_DEFILLAMA_BASE_URL: &str = "https://coins.llama.fi";
 
-// API Client responsible for fetching token prices.
-#[derive(Debug)]
+/// Longest time a request to a price provider may take before it fails.
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Returns an HTTP client whose requests fail after `timeout`.
+pub(crate) fn http_client(timeout: Duration) -> reqwest::Client {
+    // Like `reqwest::Client::new`, this only fails if the TLS backend cannot be initialized
+    reqwest::Client::builder()
+        .timeout(timeout)
+        .build()
+        .expect("HTTP client")
+}
+
+// API Client responsible for fetching token prices from DefiLlama.
+#[derive(Debug, Clone)]
 pub struct ApiClient {
-    contract_address: String,
+    http: reqwest::Client,
+    base_url: String,
+}
+
+impl Default for ApiClient {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ApiClient {
     /// Creates a new ApiClient instance.
-    pub fn new(contract_address: String) -> Self {
-        ApiClient { contract_address }
+    pub fn new() -> Self {
+        ApiClient {
+            http: http_client(DEFAULT_REQUEST_TIMEOUT),
+            base_url: DEFAULT_DEFILLAMA_BASE_URL.to_string(),
+        }
+    }
+
+    /// Fails requests taking longer than `timeout` instead of [`DEFAULT_REQUEST_TIMEOUT`].
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.http = http_client(timeout);
+        self
     }
 
-    /// Fetches the token price from an external API.
-    pub async fn fetch_price(&self) -> Result<String, AppError> {
-        let url = format!("{}:{}", API_FETCH_PRICE, self.contract_address);
-        reqwest::get(&url)
+    /// Sends requests to the given base URL instead of [`DEFAULT_DEFILLAMA_BASE_URL`],
+    /// such as a caching proxy or a local stand-in server.
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Returns the DefiLlama key of a Sui coin, e.g. `sui:0x2::sui::SUI`.
+    pub fn coin_key(contract_address: &str) -> String {
+        format!("sui:{}", contract_address)
+    }
+
+    /// Fetches the raw token price response from an external API.
+    pub async fn fetch_price(&self, contract_address: &str) -> Result<String, AppError> {
+        self.fetch_prices(&[contract_address.to_string()]).await
+    }
+
+    /// Fetches the raw price response of several tokens in a single request.
+    pub async fn fetch_prices(&self, contract_addresses: &[String]) -> Result<String, AppError> {
+        let coins: Vec<String> = contract_addresses
+            .iter()
+            .map(|address| Self::coin_key(address))
+            .collect();
+        let url = format!("{}/prices/current/{}", self.base_url, coins.join(","));
+        self.http
+            .get(&url)
+            .send()
             .await
             .map_err(|e| AppError::ApiError(format!("Error calling {}: {}", url, e)))?
             .text()
@@ -29,50 +87,111 @@ impl ApiClient {
             .map_err(|e| AppError::ApiError(format!("Error getting response from {}: {}", url, e)))
     }
 
-    /// Processes the API response and extracts token price data.
-    pub fn process_api_response(response: &str) -> Result<String, AppError> {
-        let json: Value = serde_json::from_str(response)
+    /// Processes the API response and extracts the price data of the given token.
+    ///
+    /// The price is looked up under the token's own `sui:<address>` key, so a response
+    /// holding several coins never yields the price of another token.
+    pub fn process_api_response(
+        response: &str,
+        contract_address: &str,
+    ) -> Result<PriceQuote, AppError> {
+        let coins = Self::parse_coins(response)?;
+        let processed = Self::quote_for(&coins, contract_address)?;
+
+        debug!("Processed response: {:?}", processed);
+        Ok(processed)
+    }
+
+    /// Processes a response to [`Self::fetch_prices`], mapping every requested contract
+    /// address to its price, or to the reason it has none.
+    pub fn process_batch_response(
+        response: &str,
+        contract_addresses: &[String],
+    ) -> Result<HashMap<String, Result<PriceQuote, AppError>>, AppError> {
+        let coins = Self::parse_coins(response)?;
+        Ok(contract_addresses
+            .iter()
+            .map(|address| (address.clone(), Self::quote_for(&coins, address)))
+            .collect())
+    }
+
+    /// Parses a response into its `coins` object, keyed by coin key.
+    fn parse_coins(response: &str) -> Result<Map<String, Value>, AppError> {
+        let mut json: Value = serde_json::from_str(response)
             .map_err(|e| AppError::ApiResponseError(format!("JSON parsing error: {}", e)))?;
 
-        let coins = json.get("coins").ok_or(AppError::ApiResponseError(
-            "Missing 'coins' key in response".to_string(),
-        ))?;
+        match json.get_mut("coins").map(Value::take) {
+            Some(Value::Object(coins)) => Ok(coins),
+            Some(_) => Err(AppError::ApiResponseError(
+                "'coins' is not an object in response".to_string(),
+            )),
+            None => Err(AppError::ApiResponseError(
+                "Missing 'coins' key in response".to_string(),
+            )),
+        }
+    }
 
-        let first_coin = coins
-            .as_object()
-            .and_then(|obj| obj.values().next())
-            .ok_or(AppError::ApiResponseError("No coins found".to_string()))?;
+    /// Extracts the price quote of the given token from the `coins` object.
+    fn quote_for(
+        coins: &Map<String, Value>,
+        contract_address: &str,
+    ) -> Result<PriceQuote, AppError> {
+        let key = Self::coin_key(contract_address);
+        let coin = coins.get(&key).ok_or_else(|| {
+            let returned: Vec<&str> = coins.keys().map(String::as_str).collect();
+            AppError::ApiResponseError(format!(
+                "No price for {} in response (got [{}])",
+                key,
+                returned.join(", ")
+            ))
+        })?;
+        Self::process_coin(coin)
+    }
 
+    /// Extracts the price quote of a single entry of the `coins` object.
+    fn process_coin(coin: &Value) -> Result<PriceQuote, AppError> {
         let symbol =
-            first_coin
-                .get("symbol")
+            coin.get("symbol")
                 .and_then(|s| s.as_str())
                 .ok_or(AppError::ApiResponseError(
                     "Missing symbol in response".to_string(),
                 ))?;
         let price =
-            first_coin
-                .get("price")
+            coin.get("price")
                 .and_then(|p| p.as_f64())
                 .ok_or(AppError::ApiResponseError(
                     "Missing price in response".to_string(),
                 ))?;
-        let timestamp = first_coin.get("timestamp").and_then(|t| t.as_i64()).ok_or(
-            AppError::ApiResponseError("Missing timestamp in response".to_string()),
-        )?;
+        let timestamp =
+            coin.get("timestamp")
+                .and_then(|t| t.as_i64())
+                .ok_or(AppError::ApiResponseError(
+                    "Missing timestamp in response".to_string(),
+                ))?;
 
         let date_time = Utc
             .timestamp_opt(timestamp, 0)
             .single()
             .ok_or(AppError::ApiResponseError("Invalid timestamp".to_string()))?;
 
-        let processed = serde_json::json!({
-            "symbol": symbol,
-            "price": price,
-            "timestamp": date_time.to_rfc3339()
-        });
+        // Not every coin comes with a confidence
+        let confidence = coin.get("confidence").and_then(|c| c.as_f64());
 
-        debug!("Processed response: {:?}", processed);
-        Ok(processed.to_string())
+        Ok(PriceQuote {
+            confidence,
+            ..PriceQuote::new(symbol.to_string(), price, date_time)
+        })
+    }
+}
+
+#[async_trait]
+impl PriceProvider for ApiClient {
+    fn name(&self) -> &str {
+        "defillama"
+    }
+
+    async fn fetch(&self, token: &TokenRef) -> Result<PriceQuote, AppError> {
+        let response = self.fetch_price(&token.contract_address).await?;
+        Self::process_api_response(&response, &token.contract_address)
     }
 }
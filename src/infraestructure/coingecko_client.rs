@@ -0,0 +1,195 @@
+use async_trait::async_trait;
+use chrono::{TimeZone, Utc};
+use log::{debug, warn};
+use reqwest::StatusCode;
+use serde_json::Value;
+use std::fmt;
+use std::time::Duration;
+
+use super::api_client::{http_client, DEFAULT_REQUEST_TIMEOUT};
+use crate::{
+    domain::price_provider::{PriceProvider, PriceQuote, TokenRef},
+    AppError,
+};
+
+/// Base URL of the public CoinGecko API.
+pub const DEFAULT_COINGECKO_BASE_URL: &str = "https://api.coingecko.com/api/v3";
+
+/// Header carrying the key of the CoinGecko Pro API.
+const API_KEY_HEADER: &str = "x-cg-pro-api-key";
+
+// Price provider backed by the CoinGecko simple price endpoint.
+#[derive(Clone)]
+pub struct CoinGeckoClient {
+    http: reqwest::Client,
+    base_url: String,
+    api_key: Option<String>,
+}
+
+impl fmt::Debug for CoinGeckoClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CoinGeckoClient")
+            .field("base_url", &self.base_url)
+            .field("api_key", &self.api_key.as_ref().map(|_| "<redacted>"))
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for CoinGeckoClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CoinGeckoClient {
+    /// Creates a new CoinGeckoClient instance.
+    pub fn new() -> Self {
+        CoinGeckoClient {
+            http: http_client(DEFAULT_REQUEST_TIMEOUT),
+            base_url: DEFAULT_COINGECKO_BASE_URL.to_string(),
+            api_key: None,
+        }
+    }
+
+    /// Fails requests taking longer than `timeout` instead of [`DEFAULT_REQUEST_TIMEOUT`].
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.http = http_client(timeout);
+        self
+    }
+
+    /// Sends requests to the given base URL instead of [`DEFAULT_COINGECKO_BASE_URL`],
+    /// such as the Pro API, a caching proxy or a local stand-in server.
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Authenticates every request with the given Pro API key.
+    pub fn with_api_key(mut self, api_key: &str) -> Self {
+        self.api_key = Some(api_key.to_string());
+        self
+    }
+
+    /// Calls the given API path and returns the status and raw body of the response.
+    async fn send(&self, path: &str) -> Result<(StatusCode, String), AppError> {
+        let url = format!("{}{}", self.base_url, path);
+        let mut request = self.http.get(&url);
+        if let Some(api_key) = &self.api_key {
+            request = request.header(API_KEY_HEADER, api_key);
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| AppError::ApiError(format!("Error calling {}: {}", url, e)))?;
+        let status = response.status();
+        let body = response.text().await.map_err(|e| {
+            AppError::ApiError(format!("Error getting response from {}: {}", url, e))
+        })?;
+        Ok((status, body))
+    }
+
+    /// Calls the given API path and returns the raw response, failing on error statuses
+    /// such as rate limiting.
+    async fn get(&self, path: &str) -> Result<String, AppError> {
+        let (status, body) = self.send(path).await?;
+        if !status.is_success() {
+            return Err(status_error(path, status, &body));
+        }
+        Ok(body)
+    }
+
+    /// Looks up the Sui contract address of a token, using its name as CoinGecko coin id.
+    ///
+    /// Returns `None`, logging a warning, if the token is not found on the Sui network.
+    /// Other failures, rate limiting included, are errors.
+    pub async fn fetch_contract_address(&self, token: &str) -> Result<Option<String>, AppError> {
+        let path = format!("/coins/{}", token.to_lowercase());
+        let (status, body) = self.send(&path).await?;
+        let response: Option<Value> = serde_json::from_str(&body).ok();
+
+        // Handle error if token is not found
+        let error_message = response
+            .as_ref()
+            .and_then(|r| r.get("error"))
+            .and_then(|e| e.as_str());
+        if status == StatusCode::NOT_FOUND && error_message == Some("coin not found") {
+            warn!("Token not found: {}. API response: {}", token, body);
+            return Ok(None);
+        }
+        if !status.is_success() {
+            return Err(status_error(&path, status, &body));
+        }
+        let response = response
+            .ok_or_else(|| AppError::ApiError(format!("Error parsing JSON response: {}", body)))?;
+
+        let contract_address = response
+            .pointer("/platforms/sui")
+            .and_then(|c| c.as_str())
+            .map(str::to_string);
+        if contract_address.is_none() {
+            warn!("Contract address not found for token: {}", token);
+        }
+        Ok(contract_address)
+    }
+
+    /// Processes a simple price response for the given CoinGecko coin id.
+    pub fn process_api_response(
+        response: &str,
+        coin_id: &str,
+        symbol: &str,
+    ) -> Result<PriceQuote, AppError> {
+        let json: Value = serde_json::from_str(response)
+            .map_err(|e| AppError::ApiResponseError(format!("JSON parsing error: {}", e)))?;
+
+        let coin = json.get(coin_id).ok_or(AppError::ApiResponseError(format!(
+            "Missing '{}' key in response",
+            coin_id
+        )))?;
+
+        let price = coin
+            .get("usd")
+            .and_then(|p| p.as_f64())
+            .ok_or(AppError::ApiResponseError(
+                "Missing price in response".to_string(),
+            ))?;
+        let timestamp = coin.get("last_updated_at").and_then(|t| t.as_i64()).ok_or(
+            AppError::ApiResponseError("Missing timestamp in response".to_string()),
+        )?;
+
+        let date_time = Utc
+            .timestamp_opt(timestamp, 0)
+            .single()
+            .ok_or(AppError::ApiResponseError("Invalid timestamp".to_string()))?;
+
+        let processed = PriceQuote::new(symbol.to_uppercase(), price, date_time);
+
+        debug!("Processed response: {:?}", processed);
+        Ok(processed)
+    }
+}
+
+#[async_trait]
+impl PriceProvider for CoinGeckoClient {
+    fn name(&self) -> &str {
+        "coingecko"
+    }
+
+    async fn fetch(&self, token: &TokenRef) -> Result<PriceQuote, AppError> {
+        let coin_id = token.name.to_lowercase();
+        let response = self
+            .get(&format!(
+                "/simple/price?ids={}&vs_currencies=usd&include_last_updated_at=true",
+                coin_id
+            ))
+            .await?;
+        Self::process_api_response(&response, &coin_id, &token.name)
+    }
+}
+
+/// Returns the error for a response with an error status.
+fn status_error(path: &str, status: StatusCode, body: &str) -> AppError {
+    AppError::ApiError(format!(
+        "CoinGecko returned {} for {}: {}",
+        status, path, body
+    ))
+}
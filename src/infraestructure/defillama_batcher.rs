@@ -0,0 +1,152 @@
+use async_trait::async_trait;
+use futures_util::future::{BoxFuture, FutureExt, Shared};
+use log::debug;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
+use tokio::time::{Duration, Instant};
+
+use super::api_client::ApiClient;
+use crate::{
+    domain::price_provider::{PriceProvider, PriceQuote, TokenRef},
+    AppError,
+};
+
+/// Default time the prices fetched in one batch are served before fetching them again.
+pub const DEFAULT_BATCH_ROUND: Duration = Duration::from_secs(5);
+
+/// Most coins asked for in a single request, keeping the URL to a reasonable length.
+const MAX_COINS_PER_REQUEST: usize = 50;
+
+type Prices = Arc<HashMap<String, Result<PriceQuote, AppError>>>;
+type PendingPrices = Shared<BoxFuture<'static, Result<Prices, AppError>>>;
+
+/// A batch of prices, fetched or being fetched.
+struct Round {
+    started: Instant,
+    /// Contract addresses requested in this round.
+    coins: BTreeSet<String>,
+    prices: PendingPrices,
+}
+
+#[derive(Default)]
+struct BatchState {
+    /// Contract addresses of every token asked for so far.
+    known: BTreeSet<String>,
+    round: Option<Round>,
+}
+
+// DefiLlama price provider fetching the prices of all the tokens in one request.
+//
+// The first price asked for in a round fetches the price of every token asked for so
+// far; the other tokens are served from that response until the round is over.
+#[derive(Clone)]
+pub struct BatchingApiClient {
+    client: ApiClient,
+    round: Duration,
+    /// Shorter rounds of the tokens polled too often for the default one, by symbol.
+    token_rounds: Arc<HashMap<String, Duration>>,
+    state: Arc<Mutex<BatchState>>,
+}
+
+impl std::fmt::Debug for BatchingApiClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BatchingApiClient")
+            .field("client", &self.client)
+            .field("round", &self.round)
+            .field("token_rounds", &self.token_rounds)
+            .finish_non_exhaustive()
+    }
+}
+
+impl BatchingApiClient {
+    /// Creates a batching provider sending its requests with the given client.
+    pub fn new(client: ApiClient) -> Self {
+        BatchingApiClient {
+            client,
+            round: DEFAULT_BATCH_ROUND,
+            token_rounds: Arc::new(HashMap::new()),
+            state: Arc::new(Mutex::new(BatchState::default())),
+        }
+    }
+
+    /// Sets how long the prices of a batch are served; it should be shorter than the
+    /// interval between two price requests to the same token, see [`Self::with_token_round`].
+    pub fn with_round(mut self, round: Duration) -> Self {
+        self.round = round;
+        self
+    }
+
+    /// Serves a token from rounds younger than `round` instead of the default round,
+    /// such as a token requested more often than the default round lasts.
+    pub fn with_token_round(mut self, token: &str, round: Duration) -> Self {
+        Arc::make_mut(&mut self.token_rounds).insert(token.to_uppercase(), round);
+        self
+    }
+
+    /// Returns the round covering the given token, starting a new one if needed.
+    fn round_for(&self, token: &TokenRef) -> PendingPrices {
+        let contract_address = token.contract_address.as_str();
+        let max_age = self
+            .token_rounds
+            .get(&token.name.to_uppercase())
+            .copied()
+            .unwrap_or(self.round);
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.known.insert(contract_address.to_string());
+
+        if let Some(round) = &state.round {
+            let failed = matches!(round.prices.peek(), Some(Err(_)));
+            if !failed
+                && round.started.elapsed() < max_age
+                && round.coins.contains(contract_address)
+            {
+                return round.prices.clone();
+            }
+        }
+
+        let coins = state.known.clone();
+        let prices = Self::fetch_round(self.client.clone(), coins.clone());
+        state.round = Some(Round {
+            started: Instant::now(),
+            coins,
+            prices: prices.clone(),
+        });
+        prices
+    }
+
+    /// Fetches the prices of the given tokens, in as few requests as possible.
+    fn fetch_round(client: ApiClient, coins: BTreeSet<String>) -> PendingPrices {
+        async move {
+            let coins: Vec<String> = coins.into_iter().collect();
+            let mut prices = HashMap::new();
+            for chunk in coins.chunks(MAX_COINS_PER_REQUEST) {
+                debug!("Fetching the prices of {} tokens", chunk.len());
+                let response = client.fetch_prices(chunk).await?;
+                prices.extend(ApiClient::process_batch_response(&response, chunk)?);
+            }
+            Ok(Arc::new(prices))
+        }
+        .boxed()
+        .shared()
+    }
+}
+
+#[async_trait]
+impl PriceProvider for BatchingApiClient {
+    fn name(&self) -> &str {
+        "defillama"
+    }
+
+    async fn fetch(&self, token: &TokenRef) -> Result<PriceQuote, AppError> {
+        let prices = self.round_for(token).await?;
+        prices
+            .get(&token.contract_address)
+            .cloned()
+            .unwrap_or_else(|| {
+                Err(AppError::ApiResponseError(format!(
+                    "No price for {} in batch",
+                    token.name
+                )))
+            })
+    }
+}
@@ -1 +1,6 @@
-pub mod api_client;
+pub mod address_cache;
+pub mod api_client;
+pub mod coingecko_client;
+pub mod defillama_batcher;
+pub mod price_history;
+pub mod tls;
@@ -0,0 +1,285 @@
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use log::debug;
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::{domain::price_store::PriceRecord, AppError};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS price_history (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        symbol TEXT NOT NULL,
+        contract_address TEXT NOT NULL,
+        price REAL NOT NULL,
+        sources INTEGER NOT NULL,
+        source_timestamp INTEGER NOT NULL,
+        received_at INTEGER NOT NULL,
+        published INTEGER NOT NULL DEFAULT 1
+    );
+    CREATE INDEX IF NOT EXISTS idx_price_history_symbol_time
+        ON price_history (symbol, source_timestamp);
+    CREATE TABLE IF NOT EXISTS price_rounds (
+        symbol TEXT PRIMARY KEY,
+        round_id INTEGER NOT NULL
+    );
+";
+
+/// A single stored price of a symbol.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PricePoint {
+    pub timestamp: DateTime<Utc>,
+    pub price: f64,
+    /// Whether the price was published, or held back by the publishing policy.
+    pub published: bool,
+}
+
+/// Aggregate of the price points falling into one time bucket.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PriceBucket {
+    /// Start of the bucket (inclusive).
+    pub start: DateTime<Utc>,
+    /// Last price observed in the bucket.
+    pub last: f64,
+    /// Average of the prices observed in the bucket.
+    pub avg: f64,
+    /// Number of price points in the bucket.
+    pub count: usize,
+}
+
+// Persistent, append-only history of the prices accepted by the server, published or not.
+#[derive(Debug, Clone)]
+pub struct PriceHistory {
+    conn: Arc<Mutex<Connection>>,
+    retention: Option<Duration>,
+}
+
+impl PriceHistory {
+    /// Opens (or creates) the history database at the given path.
+    ///
+    /// # Arguments
+    /// * `path` - Path of the SQLite database file.
+    /// * `retention` - How long records are kept; `None` keeps them forever.
+    pub fn open(path: &str, retention: Option<Duration>) -> Result<Self, AppError> {
+        let conn = Connection::open(path).map_err(|e| {
+            AppError::DatabaseError(format!("Error opening database {}: {}", path, e))
+        })?;
+        Self::from_connection(conn, retention)
+    }
+
+    /// Opens a history kept in memory, mostly useful for tests.
+    pub fn open_in_memory(retention: Option<Duration>) -> Result<Self, AppError> {
+        let conn = Connection::open_in_memory().map_err(|e| {
+            AppError::DatabaseError(format!("Error opening in-memory database: {}", e))
+        })?;
+        Self::from_connection(conn, retention)
+    }
+
+    fn from_connection(conn: Connection, retention: Option<Duration>) -> Result<Self, AppError> {
+        conn.execute_batch(SCHEMA)
+            .map_err(|e| AppError::DatabaseError(format!("Error creating schema: {}", e)))?;
+        // Databases created before reports held back by the publishing policy were kept
+        // only hold published prices
+        let has_published: bool = conn
+            .query_row(
+                "SELECT COUNT(*) FROM pragma_table_info('price_history') WHERE name = 'published'",
+                [],
+                |row| row.get::<_, i64>(0),
+            )
+            .map(|count| count > 0)
+            .map_err(|e| AppError::DatabaseError(format!("Error reading schema: {}", e)))?;
+        if !has_published {
+            conn.execute(
+                "ALTER TABLE price_history ADD COLUMN published INTEGER NOT NULL DEFAULT 1",
+                [],
+            )
+            .map_err(|e| AppError::DatabaseError(format!("Error migrating schema: {}", e)))?;
+        }
+        Ok(PriceHistory {
+            conn: Arc::new(Mutex::new(conn)),
+            retention,
+        })
+    }
+
+    /// Appends an accepted price record to the history, remembering the round of its
+    /// attestation, if any.
+    ///
+    /// # Arguments
+    /// * `record` - The record of the report.
+    /// * `published` - Whether the report became the published price, or was held back
+    ///   by the publishing policy or a newer quote.
+    pub fn append(&self, record: &PriceRecord, published: bool) -> Result<(), AppError> {
+        let mut conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        let tx = conn
+            .transaction()
+            .map_err(|e| AppError::DatabaseError(format!("Error appending price: {}", e)))?;
+        tx.execute(
+            "INSERT INTO price_history
+                (symbol, contract_address, price, sources, source_timestamp, received_at,
+                 published)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                record.symbol,
+                record.contract_address,
+                record.price,
+                record.sources as i64,
+                record.timestamp.timestamp_millis(),
+                record.received_at.timestamp_millis(),
+                published,
+            ],
+        )
+        .map_err(|e| AppError::DatabaseError(format!("Error appending price: {}", e)))?;
+
+        if let Some(attestation) = &record.attestation {
+            tx.execute(
+                "INSERT INTO price_rounds (symbol, round_id) VALUES (?1, ?2)
+                 ON CONFLICT (symbol) DO UPDATE SET round_id = MAX(round_id, excluded.round_id)",
+                params![record.symbol, attestation.round_id as i64],
+            )
+            .map_err(|e| AppError::DatabaseError(format!("Error saving price round: {}", e)))?;
+        }
+        tx.commit()
+            .map_err(|e| AppError::DatabaseError(format!("Error appending price: {}", e)))
+    }
+
+    /// Returns the last attested round of every symbol, kept regardless of the retention.
+    pub fn last_rounds(&self) -> Result<HashMap<String, u64>, AppError> {
+        let conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        let mut statement = conn
+            .prepare("SELECT symbol, round_id FROM price_rounds")
+            .map_err(|e| AppError::DatabaseError(format!("Error preparing query: {}", e)))?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as u64))
+            })
+            .map_err(|e| AppError::DatabaseError(format!("Error querying rounds: {}", e)))?;
+        rows.collect::<Result<_, _>>()
+            .map_err(|e| AppError::DatabaseError(format!("Error reading rounds: {}", e)))
+    }
+
+    /// Returns the number of records stored for a symbol.
+    pub fn count(&self, symbol: &str) -> Result<usize, AppError> {
+        let conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        conn.query_row(
+            "SELECT COUNT(*) FROM price_history WHERE symbol = ?1",
+            params![symbol.to_uppercase()],
+            |row| row.get::<_, i64>(0),
+        )
+        .map(|count| count as usize)
+        .map_err(|e| AppError::DatabaseError(format!("Error counting prices: {}", e)))
+    }
+
+    /// Returns the price points of a symbol whose source timestamp lies in `[from, to]`,
+    /// ordered by timestamp.
+    pub fn range(
+        &self,
+        symbol: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<PricePoint>, AppError> {
+        let conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        let mut statement = conn
+            .prepare(
+                "SELECT source_timestamp, price, published FROM price_history
+                 WHERE symbol = ?1 AND source_timestamp BETWEEN ?2 AND ?3
+                 ORDER BY source_timestamp, id",
+            )
+            .map_err(|e| AppError::DatabaseError(format!("Error preparing query: {}", e)))?;
+
+        let rows = statement
+            .query_map(
+                params![
+                    symbol.to_uppercase(),
+                    from.timestamp_millis(),
+                    to.timestamp_millis()
+                ],
+                |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        row.get::<_, f64>(1)?,
+                        row.get::<_, bool>(2)?,
+                    ))
+                },
+            )
+            .map_err(|e| AppError::DatabaseError(format!("Error querying history: {}", e)))?;
+
+        let mut points = Vec::new();
+        for row in rows {
+            let (millis, price, published) =
+                row.map_err(|e| AppError::DatabaseError(format!("Error reading history: {}", e)))?;
+            let timestamp =
+                Utc.timestamp_millis_opt(millis)
+                    .single()
+                    .ok_or(AppError::DatabaseError(format!(
+                        "Invalid timestamp in history: {}",
+                        millis
+                    )))?;
+            points.push(PricePoint {
+                timestamp,
+                price,
+                published,
+            });
+        }
+        Ok(points)
+    }
+
+    /// Deletes the records received before the retention window.
+    ///
+    /// # Returns
+    /// * The number of deleted records (always 0 when no retention is configured).
+    pub fn prune(&self) -> Result<usize, AppError> {
+        let Some(retention) = self.retention else {
+            return Ok(0);
+        };
+        // Nothing can be older than a retention reaching before the earliest date
+        let Some(cutoff) = Utc::now().checked_sub_signed(retention) else {
+            return Ok(0);
+        };
+        let cutoff = cutoff.timestamp_millis();
+
+        let conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        let deleted = conn
+            .execute(
+                "DELETE FROM price_history WHERE received_at < ?1",
+                params![cutoff],
+            )
+            .map_err(|e| AppError::DatabaseError(format!("Error pruning history: {}", e)))?;
+
+        debug!("Pruned {} price history records", deleted);
+        Ok(deleted)
+    }
+}
+
+/// Groups ordered price points into buckets of `interval`, aligned to the Unix epoch.
+///
+/// Empty buckets are omitted.
+pub fn downsample(points: &[PricePoint], interval: Duration) -> Vec<PriceBucket> {
+    let step = interval.num_milliseconds().max(1);
+    let mut buckets: Vec<PriceBucket> = Vec::new();
+
+    for point in points {
+        let millis = point.timestamp.timestamp_millis();
+        let start_millis = millis - millis.rem_euclid(step);
+
+        match buckets.last_mut() {
+            Some(bucket) if bucket.start.timestamp_millis() == start_millis => {
+                bucket.avg =
+                    (bucket.avg * bucket.count as f64 + point.price) / (bucket.count + 1) as f64;
+                bucket.last = point.price;
+                bucket.count += 1;
+            }
+            _ => {
+                if let Some(start) = Utc.timestamp_millis_opt(start_millis).single() {
+                    buckets.push(PriceBucket {
+                        start,
+                        last: point.price,
+                        avg: point.price,
+                        count: 1,
+                    });
+                }
+            }
+        }
+    }
+    buckets
+}
@@ -0,0 +1,162 @@
+// tls.rs
+use rustls::crypto::ring::default_provider;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{ClientConfig, RootCertStore, ServerConfig};
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+
+use crate::AppError;
+
+/// TLS settings of the WebSocket server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerTlsConfig {
+    /// PEM file with the server certificate chain.
+    pub cert_path: String,
+    /// PEM file with the server private key.
+    pub key_path: String,
+    /// PEM file with the CA certificates clients must present a certificate from.
+    /// When set, clients without a valid certificate are refused (mutual TLS).
+    pub client_ca_path: Option<String>,
+}
+
+impl ServerTlsConfig {
+    /// Creates TLS settings without client authentication.
+    pub fn new(cert_path: &str, key_path: &str) -> Self {
+        Self {
+            cert_path: cert_path.to_string(),
+            key_path: key_path.to_string(),
+            client_ca_path: None,
+        }
+    }
+
+    /// Requires clients to present a certificate issued by a CA of the given file.
+    pub fn with_client_ca(mut self, ca_path: &str) -> Self {
+        self.client_ca_path = Some(ca_path.to_string());
+        self
+    }
+
+    /// Loads the certificates and keys into a rustls server configuration.
+    pub fn load(&self) -> Result<Arc<ServerConfig>, AppError> {
+        let provider = Arc::new(default_provider());
+        let builder = ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(|e| AppError::TlsError(e.to_string()))?;
+
+        let builder = match &self.client_ca_path {
+            Some(ca_path) => {
+                let roots = load_roots(ca_path)?;
+                let verifier =
+                    WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                        .build()
+                        .map_err(|e| AppError::TlsError(format!("Invalid client CA: {}", e)))?;
+                builder.with_client_cert_verifier(verifier)
+            }
+            None => builder.with_no_client_auth(),
+        };
+
+        let config = builder
+            .with_single_cert(load_certs(&self.cert_path)?, load_key(&self.key_path)?)
+            .map_err(|e| AppError::TlsError(format!("Invalid server certificate: {}", e)))?;
+        Ok(Arc::new(config))
+    }
+}
+
+/// TLS settings of a client connecting with `wss://`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientTlsConfig {
+    /// PEM file with the CA certificates trusted to sign the server certificate.
+    /// The Mozilla root certificates are trusted when not set.
+    pub ca_path: Option<String>,
+    /// PEM file with the client certificate chain, for mutual TLS.
+    pub cert_path: Option<String>,
+    /// PEM file with the client private key, for mutual TLS.
+    pub key_path: Option<String>,
+}
+
+impl ClientTlsConfig {
+    /// Creates TLS settings trusting the Mozilla root certificates.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Trusts the CA certificates of the given file instead of the Mozilla roots.
+    pub fn with_ca(mut self, ca_path: &str) -> Self {
+        self.ca_path = Some(ca_path.to_string());
+        self
+    }
+
+    /// Presents the given certificate to servers requiring mutual TLS.
+    pub fn with_client_cert(mut self, cert_path: &str, key_path: &str) -> Self {
+        self.cert_path = Some(cert_path.to_string());
+        self.key_path = Some(key_path.to_string());
+        self
+    }
+
+    /// Loads the certificates and keys into a rustls client configuration.
+    pub fn load(&self) -> Result<Arc<ClientConfig>, AppError> {
+        let roots = match &self.ca_path {
+            Some(ca_path) => load_roots(ca_path)?,
+            None => RootCertStore {
+                roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+            },
+        };
+
+        let builder = ClientConfig::builder_with_provider(Arc::new(default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(|e| AppError::TlsError(e.to_string()))?
+            .with_root_certificates(roots);
+
+        let config = match (&self.cert_path, &self.key_path) {
+            (Some(cert_path), Some(key_path)) => builder
+                .with_client_auth_cert(load_certs(cert_path)?, load_key(key_path)?)
+                .map_err(|e| AppError::TlsError(format!("Invalid client certificate: {}", e)))?,
+            (None, None) => builder.with_no_client_auth(),
+            _ => {
+                return Err(AppError::TlsError(
+                    "A client certificate needs both a certificate and a key".to_string(),
+                ))
+            }
+        };
+        Ok(Arc::new(config))
+    }
+}
+
+fn open(path: &str) -> Result<BufReader<File>, AppError> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|e| AppError::FileError(format!("Error opening file {}: {}", path, e)))
+}
+
+/// Reads every certificate of a PEM file.
+fn load_certs(path: &str) -> Result<Vec<CertificateDer<'static>>, AppError> {
+    let certs = rustls_pemfile::certs(&mut open(path)?)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| AppError::TlsError(format!("Error reading certificates {}: {}", path, e)))?;
+    if certs.is_empty() {
+        return Err(AppError::TlsError(format!(
+            "No certificate found in {}",
+            path
+        )));
+    }
+    Ok(certs)
+}
+
+/// Reads the first private key of a PEM file.
+fn load_key(path: &str) -> Result<PrivateKeyDer<'static>, AppError> {
+    rustls_pemfile::private_key(&mut open(path)?)
+        .map_err(|e| AppError::TlsError(format!("Error reading private key {}: {}", path, e)))?
+        .ok_or_else(|| AppError::TlsError(format!("No private key found in {}", path)))
+}
+
+/// Reads the CA certificates of a PEM file into a root store.
+fn load_roots(path: &str) -> Result<RootCertStore, AppError> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(path)? {
+        roots.add(cert).map_err(|e| {
+            AppError::TlsError(format!("Invalid CA certificate in {}: {}", path, e))
+        })?;
+    }
+    Ok(roots)
+}
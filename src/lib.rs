@@ -6,7 +6,7 @@ pub mod infraestructure;
 use std::fmt;
 
 /// Enum representing various application errors.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum AppError {
     /// Error in TCP connection (e.g., binding or accepting a connection)
     TcpError(String),
@@ -34,6 +34,33 @@ pub enum AppError {
 
     /// Error while processing a JSON response (e.g., parsing)
     JsonError(String),
+
+    /// Error while reading or writing the price history database
+    DatabaseError(String),
+
+    /// Error while serving the HTTP API
+    HttpError(String),
+
+    /// Malformed or unexpected protocol message
+    ProtocolError(String),
+
+    /// Protocol message written with an unsupported protocol version
+    ProtocolVersionError(String),
+
+    /// Client registration rejected by the server
+    RegistrationError(String),
+
+    /// Error while listening for process signals
+    SignalError(String),
+
+    /// Missing, malformed or invalid signature
+    SignatureError(String),
+
+    /// Error while loading TLS certificates or establishing a TLS session
+    TlsError(String),
+
+    /// Missing or invalid configuration setting
+    ConfigError(String),
 }
 
 impl fmt::Display for AppError {
@@ -49,6 +76,15 @@ impl fmt::Display for AppError {
             AppError::UnknownError(msg) => write!(f, "Unknown Error: {}", msg),
             AppError::FileError(msg) => write!(f, "File Handling Error: {}", msg),
             AppError::JsonError(msg) => write!(f, "JSON Processing Error: {}", msg),
+            AppError::DatabaseError(msg) => write!(f, "Database Error: {}", msg),
+            AppError::HttpError(msg) => write!(f, "HTTP Error: {}", msg),
+            AppError::ProtocolError(msg) => write!(f, "Protocol Error: {}", msg),
+            AppError::ProtocolVersionError(msg) => write!(f, "Protocol Version Error: {}", msg),
+            AppError::RegistrationError(msg) => write!(f, "Registration Error: {}", msg),
+            AppError::SignalError(msg) => write!(f, "Signal Error: {}", msg),
+            AppError::SignatureError(msg) => write!(f, "Signature Error: {}", msg),
+            AppError::TlsError(msg) => write!(f, "TLS Error: {}", msg),
+            AppError::ConfigError(msg) => write!(f, "Configuration Error: {}", msg),
         }
     }
 }
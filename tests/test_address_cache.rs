@@ -0,0 +1,45 @@
+use chrono::Duration;
+use suicrypto_oracle::infraestructure::address_cache::AddressCache;
+
+// Test that resolved addresses survive a restart and expire after the TTL
+#[test]
+fn test_address_cache_persists_and_expires() {
+    let path = std::env::temp_dir().join(format!("address-cache-{}.json", std::process::id()));
+    std::fs::remove_file(&path).ok();
+
+    let mut cache = AddressCache::open(&path, Duration::days(1));
+    assert_eq!(cache.get("SUI"), None);
+    cache.insert("sui", "0x2::sui::SUI");
+    cache.save().expect("Error saving cache");
+
+    let reopened = AddressCache::open(&path, Duration::days(1));
+    assert_eq!(reopened.get("SUI"), Some("0x2::sui::SUI"));
+
+    // Expired entries are only handed out on request, as a fallback
+    let expired = AddressCache::open(&path, Duration::zero());
+    assert_eq!(expired.get("SUI"), None);
+    assert_eq!(
+        expired.get_stale("SUI").map(|e| e.coin_type.as_str()),
+        Some("0x2::sui::SUI")
+    );
+
+    std::fs::remove_file(&path).ok();
+}
+
+// Test that a corrupted cache file is ignored instead of failing startup
+#[test]
+fn test_address_cache_ignores_unreadable_file() {
+    let path = std::env::temp_dir().join(format!("address-cache-bad-{}.json", std::process::id()));
+    std::fs::write(&path, "not json").unwrap();
+
+    let mut cache = AddressCache::open(&path, Duration::days(1));
+    assert_eq!(cache.get("SUI"), None);
+    cache.insert("SUI", "0x2::sui::SUI");
+    cache.save().expect("Error saving cache");
+    assert_eq!(
+        AddressCache::open(&path, Duration::days(1)).get("SUI"),
+        Some("0x2::sui::SUI")
+    );
+
+    std::fs::remove_file(&path).ok();
+}
@@ -0,0 +1,144 @@
+use async_trait::async_trait;
+use chrono::{TimeZone, Utc};
+use std::sync::Arc;
+use std::time::Duration;
+
+use suicrypto_oracle::{
+    application::price_aggregator::PriceAggregator,
+    domain::{
+        price_provider::{PriceProvider, PriceQuote, TokenRef},
+        quote_policy::{FlagReason, QuotePolicy},
+    },
+    AppError,
+};
+
+// Provider returning a fixed price, used to simulate upstream sources
+#[derive(Debug)]
+struct FixedProvider(Option<f64>);
+
+#[async_trait]
+impl PriceProvider for FixedProvider {
+    fn name(&self) -> &str {
+        "fixed"
+    }
+
+    async fn fetch(&self, token: &TokenRef) -> Result<PriceQuote, AppError> {
+        match self.0 {
+            Some(price) => Ok(PriceQuote::new(
+                token.name.clone(),
+                price,
+                Utc.timestamp_opt(1732000000, 0).unwrap(),
+            )),
+            None => Err(AppError::ApiError("upstream unavailable".to_string())),
+        }
+    }
+}
+
+fn token() -> TokenRef {
+    TokenRef::new("SUI".to_string(), "0x2::sui::SUI".to_string())
+}
+
+// Test that a quote outside the deviation band is dropped from the median
+#[tokio::test]
+async fn test_aggregator_rejects_outliers() {
+    let providers: Vec<Arc<dyn PriceProvider>> = vec![
+        Arc::new(FixedProvider(Some(3.00))),
+        Arc::new(FixedProvider(Some(3.02))),
+        Arc::new(FixedProvider(Some(9.99))),
+        Arc::new(FixedProvider(None)),
+    ];
+    let aggregator = PriceAggregator::new(providers, 200, 2);
+
+    let quote = aggregator.fetch(&token()).await.expect("Error aggregating");
+
+    assert_eq!(quote.sources, 2);
+    assert!((quote.price - 3.01).abs() < 1e-9);
+}
+
+// Test that aggregation fails when too few sources agree
+#[tokio::test]
+async fn test_aggregator_requires_min_sources() {
+    let providers: Vec<Arc<dyn PriceProvider>> = vec![
+        Arc::new(FixedProvider(Some(3.00))),
+        Arc::new(FixedProvider(None)),
+    ];
+    let aggregator = PriceAggregator::new(providers, 200, 2);
+
+    assert!(aggregator.fetch(&token()).await.is_err());
+}
+
+// Provider returning a given quote
+#[derive(Debug)]
+struct QuoteProvider(PriceQuote);
+
+#[async_trait]
+impl PriceProvider for QuoteProvider {
+    fn name(&self) -> &str {
+        "quote"
+    }
+
+    async fn fetch(&self, _token: &TokenRef) -> Result<PriceQuote, AppError> {
+        Ok(self.0.clone())
+    }
+}
+
+fn quote_at(price: f64, age_secs: i64, confidence: Option<f64>) -> PriceQuote {
+    PriceQuote {
+        confidence,
+        ..PriceQuote::new(
+            "SUI".to_string(),
+            price,
+            Utc::now() - chrono::Duration::seconds(age_secs),
+        )
+    }
+}
+
+// Test that stale or low-confidence quotes are excluded, and flagged if nothing else is left
+#[tokio::test]
+async fn test_aggregator_applies_quote_policy() {
+    let policy = QuotePolicy::new(Some(Duration::from_secs(60)), Some(0.9));
+    let providers: Vec<Arc<dyn PriceProvider>> = vec![
+        Arc::new(QuoteProvider(quote_at(3.00, 5, Some(0.95)))),
+        Arc::new(QuoteProvider(quote_at(3.04, 600, Some(0.99)))),
+        Arc::new(QuoteProvider(quote_at(3.02, 5, Some(0.5)))),
+    ];
+    let aggregator = PriceAggregator::new(providers, 200, 1).with_token_quote_policy("sui", policy);
+
+    let quote = aggregator.fetch(&token()).await.expect("Error aggregating");
+    assert_eq!((quote.price, quote.sources), (3.00, 1));
+    assert_eq!(quote.confidence, Some(0.95));
+    assert!(quote.flags.is_empty());
+
+    // Without any quote meeting the policy, the price is reported with its flags
+    let providers: Vec<Arc<dyn PriceProvider>> = vec![
+        Arc::new(QuoteProvider(quote_at(3.04, 600, Some(0.99)))),
+        Arc::new(QuoteProvider(quote_at(3.02, 5, Some(0.5)))),
+    ];
+    let aggregator = PriceAggregator::new(providers, 200, 1).with_quote_policy(policy);
+
+    let quote = aggregator.fetch(&token()).await.expect("Error aggregating");
+    let reasons: Vec<FlagReason> = quote.flags.iter().map(|f| f.reason).collect();
+    assert_eq!(reasons, vec![FlagReason::Stale, FlagReason::LowConfidence]);
+    assert!(quote.flags[0].message.starts_with("quote: "));
+}
+
+// Test that the aggregated quote is named after the token, whatever the providers call it
+#[tokio::test]
+async fn test_aggregator_reports_the_token_name() {
+    let upstream = |symbol: &str, price: f64, timestamp: i64| {
+        PriceQuote::new(
+            symbol.to_string(),
+            price,
+            Utc.timestamp_opt(timestamp, 0).unwrap(),
+        )
+    };
+    let providers: Vec<Arc<dyn PriceProvider>> = vec![
+        Arc::new(QuoteProvider(upstream("SUI", 3.00, 1732000000))),
+        Arc::new(QuoteProvider(upstream("sui-network", 3.01, 1732000010))),
+    ];
+    let aggregator = PriceAggregator::new(providers, 200, 2);
+
+    let quote = aggregator.fetch(&token()).await.expect("Error aggregating");
+    assert_eq!(quote.symbol, "SUI");
+    assert_eq!(quote.timestamp.timestamp(), 1732000010);
+}
@@ -1,3 +1,6 @@
+// The health checks predate the lint settings and are kept as written
+#![allow(clippy::single_component_path_imports, clippy::bool_assert_comparison)]
+
 use reqwest::Client;
 use tokio;
 
@@ -69,3 +72,130 @@ async fn test_coinllama_healthcheck() {
         "Response body doesn't contain expected data"
     );
 }
+
+// Test that a DefiLlama response is parsed into a typed price quote
+#[test]
+fn test_process_api_response_returns_quote() {
+    use suicrypto_oracle::infraestructure::api_client::ApiClient;
+
+    let response = r#"{"coins":{"sui:0x2::sui::SUI":{"decimals":9,"symbol":"SUI","price":3.5,"timestamp":1732000000,"confidence":0.99}}}"#;
+
+    let quote = ApiClient::process_api_response(response, "0x2::sui::SUI")
+        .expect("Error processing response");
+
+    assert_eq!(quote.symbol, "SUI");
+    assert_eq!(quote.price, 3.5);
+    assert_eq!(quote.timestamp.timestamp(), 1732000000);
+    assert_eq!(quote.confidence, Some(0.99));
+}
+
+// Test that the price is read under the requested coin key, never another coin's
+#[test]
+fn test_process_api_response_maps_coin_keys() {
+    use suicrypto_oracle::{infraestructure::api_client::ApiClient, AppError};
+
+    let response = r#"{"coins":{
+        "sui:0xdee9::deep::DEEP":{"symbol":"DEEP","price":0.2,"timestamp":1732000000},
+        "sui:0x2::sui::SUI":{"symbol":"SUI","price":3.5,"timestamp":1732000000}
+    }}"#;
+
+    let sui = ApiClient::process_api_response(response, "0x2::sui::SUI").unwrap();
+    assert_eq!((sui.symbol.as_str(), sui.price), ("SUI", 3.5));
+    let deep = ApiClient::process_api_response(response, "0xdee9::deep::DEEP").unwrap();
+    assert_eq!((deep.symbol.as_str(), deep.price), ("DEEP", 0.2));
+
+    let missing = ApiClient::process_api_response(response, "0x3::nope::NOPE").unwrap_err();
+    assert!(matches!(
+        missing,
+        AppError::ApiResponseError(msg) if msg.contains("sui:0x3::nope::NOPE")
+    ));
+    assert!(ApiClient::process_api_response(r#"{"coins":{}}"#, "0x2::sui::SUI").is_err());
+}
+
+// Test that both providers and the contract lookup call the configured base URLs,
+// sending the CoinGecko Pro API key
+#[tokio::test]
+async fn test_clients_use_configured_base_urls() {
+    use axum::{
+        extract::Path,
+        http::{HeaderMap, StatusCode},
+        routing::get,
+        Json, Router,
+    };
+    use serde_json::{json, Value};
+    use suicrypto_oracle::{
+        domain::price_provider::{PriceProvider, TokenRef},
+        infraestructure::{api_client::ApiClient, coingecko_client::CoinGeckoClient},
+    };
+
+    let authorized = |headers: &HeaderMap| {
+        headers
+            .get("x-cg-pro-api-key")
+            .and_then(|v| v.to_str().ok())
+            == Some("secret")
+    };
+    let app = Router::new()
+        .route(
+            "/llama/prices/current/{coins}",
+            get(|Path(coins): Path<String>| async move {
+                Json(json!({"coins": {coins: {"symbol": "SUI", "price": 3.5, "timestamp": 1732000000}}}))
+            }),
+        )
+        .route(
+            "/gecko/coins/{id}",
+            get(move |headers: HeaderMap, Path(id): Path<String>| async move {
+                match id.as_str() {
+                    _ if !authorized(&headers) => {
+                        (StatusCode::UNAUTHORIZED, Json(json!({"error": "unauthorized"})))
+                    }
+                    "nope" => (StatusCode::NOT_FOUND, Json(json!({"error": "coin not found"}))),
+                    "busy" => (
+                        StatusCode::TOO_MANY_REQUESTS,
+                        Json(json!({"status": {"error_code": 429}})),
+                    ),
+                    _ => (
+                        StatusCode::OK,
+                        Json(json!({"id": id, "platforms": {"sui": "0x2::sui::SUI"}})),
+                    ),
+                }
+            }),
+        )
+        .route(
+            "/gecko/simple/price",
+            get(move |headers: HeaderMap| async move {
+                let price: Value = if authorized(&headers) { json!(3.6) } else { json!(null) };
+                Json(json!({"sui": {"usd": price, "last_updated_at": 1732000000}}))
+            }),
+        );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    let server = tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let token = TokenRef::new("SUI".to_string(), "0x2::sui::SUI".to_string());
+    let defillama = ApiClient::new().with_base_url(&format!("{}/llama/", base_url));
+    assert_eq!(defillama.fetch(&token).await.unwrap().price, 3.5);
+
+    let coingecko = CoinGeckoClient::new()
+        .with_base_url(&format!("{}/gecko", base_url))
+        .with_api_key("secret");
+    assert_eq!(coingecko.fetch(&token).await.unwrap().price, 3.6);
+    assert_eq!(
+        coingecko.fetch_contract_address("SUI").await.unwrap(),
+        Some("0x2::sui::SUI".to_string())
+    );
+    assert!(!format!("{:?}", coingecko).contains("secret"));
+
+    // Only a missing coin is not found, rate limiting is an error
+    assert_eq!(
+        coingecko.fetch_contract_address("NOPE").await.unwrap(),
+        None
+    );
+    assert!(coingecko.fetch_contract_address("BUSY").await.is_err());
+    assert!(CoinGeckoClient::new()
+        .with_base_url(&format!("{}/gecko", base_url))
+        .fetch_contract_address("SUI")
+        .await
+        .is_err());
+
+    server.abort();
+}
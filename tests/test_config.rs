@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use suicrypto_oracle::config::{Config, ConfigArgs, TokenConfig};
+use suicrypto_oracle::AppError;
+
+// Test that tokens can be listed by name or with their own polling settings
+#[test]
+fn test_config_accepts_detailed_token_entries() {
+    let path = std::env::temp_dir().join(format!("tokens-{}.json", std::process::id()));
+    std::fs::write(
+        &path,
+        r#"{"tokens": ["DEEP", {"symbol": "SUI", "coin_type": "0x2::sui::SUI", "interval_secs": 5, "jitter_secs": 1}]}"#,
+    )
+    .unwrap();
+
+    let config = Config::load_from_file(path.to_str().unwrap()).expect("Error loading config");
+    std::fs::remove_file(&path).ok();
+
+    assert_eq!(config.token_names(), vec!["DEEP", "SUI"]);
+    assert_eq!(
+        config.tokens[1],
+        TokenConfig {
+            symbol: "SUI".to_string(),
+            coin_type: Some("0x2::sui::SUI".to_string()),
+            interval_secs: Some(5),
+            jitter_secs: Some(1),
+            max_age_secs: None,
+            min_confidence: None,
+            deviation_bps: None,
+            heartbeat_secs: None,
+        }
+    );
+    assert_eq!(config.tokens[0].interval_secs, None);
+    assert_eq!(config.tokens[0].coin_type, None);
+    assert!(config.validate().is_ok());
+
+    // SUI is polled more often than the default batch round allows
+    assert_eq!(
+        config.token_batch_rounds(),
+        vec![("SUI".to_string(), Duration::from_millis(2500))]
+    );
+}
+
+// Test that environment variables override the file and flags override both
+#[test]
+fn test_config_layers_file_env_and_flags() {
+    let path = std::env::temp_dir().join(format!("config-{}.json", std::process::id()));
+    std::fs::write(
+        &path,
+        r#"{
+            "server": {"host": "0.0.0.0:9000", "request_interval_secs": 30},
+            "client": {"providers": ["coingecko"]},
+            "tokens": ["SUI"]
+        }"#,
+    )
+    .unwrap();
+
+    let mut config = Config::load_from_file(path.to_str().unwrap()).expect("Error loading config");
+    std::fs::remove_file(&path).ok();
+    assert_eq!(config.server.host, "0.0.0.0:9000");
+    assert_eq!(config.server.http_host, "127.0.0.1:8081");
+
+    let env: HashMap<&str, &str> = [
+        ("SERVER_HOST", "10.0.0.1:8080"),
+        ("PRICE_PROVIDERS", "defillama, coingecko"),
+        ("HEARTBEAT_TIMEOUT_SECS", "60"),
+        ("REQUEST_TIMEOUT_SECS", "3"),
+    ]
+    .into();
+    config
+        .apply_env(|name| env.get(name).map(|v| v.to_string()))
+        .expect("Error applying environment");
+    assert_eq!(config.server.host, "10.0.0.1:8080");
+    assert_eq!(config.client.providers, vec!["defillama", "coingecko"]);
+    assert_eq!(config.heartbeat.timeout_secs, 60);
+    assert_eq!(config.client.request_timeout(), Duration::from_secs(3));
+
+    config.apply_args(&ConfigArgs {
+        server_host: Some("10.0.0.2:8080".to_string()),
+        request_interval_secs: Some(5),
+        ..Default::default()
+    });
+    assert_eq!(config.server.host, "10.0.0.2:8080");
+    assert_eq!(config.server.request_interval_secs, 5);
+    assert!(config.validate().is_ok());
+}
+
+// Test that invalid settings are reported at startup
+#[test]
+fn test_config_rejects_invalid_settings() {
+    let error = Config::default()
+        .apply_env(|name| (name == "MIN_SOURCES").then(|| "two".to_string()))
+        .unwrap_err();
+    assert!(matches!(error, AppError::ConfigError(msg) if msg.contains("MIN_SOURCES")));
+
+    let invalid = |change: fn(&mut Config)| {
+        let mut config = Config::default();
+        change(&mut config);
+        config.validate().unwrap_err().to_string()
+    };
+    assert!(invalid(|c| c.server.host = "localhost".to_string()).contains("server.host"));
+    assert!(invalid(|c| c.client.providers = vec!["binance".to_string()]).contains("binance"));
+    assert!(invalid(|c| c.client.min_sources = 2).contains("min_sources"));
+    assert!(invalid(|c| c.heartbeat.timeout_secs = 1).contains("timeout_secs"));
+    assert!(invalid(|c| c.client.request_timeout_secs = 0).contains("request_timeout_secs"));
+    assert!(
+        invalid(|c| c.server.history_retention_days = Some(i64::MAX))
+            .contains("history_retention_days must be at most")
+    );
+    assert!(invalid(|c| c.heartbeat.timeout_secs = u64::MAX).contains("at most"));
+    assert!(invalid(|c| c.server.request_interval_secs = u64::MAX)
+        .contains("request_interval_secs must be at most"));
+    assert!(invalid(|c| c.tokens =
+        serde_json::from_str(r#"[{"symbol": "SUI", "interval_secs": 18446744073709551615}]"#)
+            .unwrap())
+    .contains("Token SUI: interval_secs must be at most"));
+    assert!(invalid(|c| c.publishing.heartbeat_secs = Some(0)).contains("publishing"));
+    assert!(
+        invalid(|c| c.tokens = serde_json::from_str(r#"["SUI", "sui"]"#).unwrap())
+            .contains("listed twice")
+    );
+
+    let path = std::env::temp_dir().join(format!("config-typo-{}.json", std::process::id()));
+    std::fs::write(&path, r#"{"server": {"hots": "127.0.0.1:8080"}}"#).unwrap();
+    let error = Config::load_from_file(path.to_str().unwrap()).unwrap_err();
+    std::fs::remove_file(&path).ok();
+    assert!(matches!(error, AppError::ConfigError(msg) if msg.contains("hots")));
+}
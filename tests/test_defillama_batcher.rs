@@ -0,0 +1,99 @@
+use axum::{extract::Path, routing::get, Json, Router};
+use serde_json::{json, Map, Value};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use suicrypto_oracle::{
+    domain::price_provider::{PriceProvider, TokenRef},
+    infraestructure::{api_client::ApiClient, defillama_batcher::BatchingApiClient},
+};
+
+/// Serves DefiLlama prices for the known coins, listed in reverse order and followed by
+/// an unrequested coin, and counts the requests.
+async fn stand_in_server(requests: Arc<AtomicUsize>) -> String {
+    let prices = [
+        ("0x2::sui::SUI", "SUI", 3.5),
+        ("0xdee9::deep::DEEP", "DEEP", 0.2),
+    ];
+    let app = Router::new().route(
+        "/prices/current/{coins}",
+        get(move |Path(coins): Path<String>| async move {
+            requests.fetch_add(1, Ordering::SeqCst);
+            let mut body = Map::new();
+            for key in coins.split(',').rev() {
+                if let Some((_, symbol, price)) = prices.iter().find(|(a, ..)| key.ends_with(a)) {
+                    body.insert(
+                        key.to_string(),
+                        json!({"symbol": symbol, "price": price, "timestamp": 1732000000}),
+                    );
+                }
+            }
+            body.insert(
+                "sui:0x1::other::OTHER".to_string(),
+                json!({"symbol": "OTHER", "price": 99.0, "timestamp": 1732000000}),
+            );
+            Json(json!({ "coins": Value::Object(body) }))
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await });
+    base_url
+}
+
+// Test that the tokens of a round share one request and get their own price back
+#[tokio::test]
+async fn test_batcher_fetches_known_tokens_in_one_request() {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let base_url = stand_in_server(requests.clone()).await;
+    let batcher = BatchingApiClient::new(ApiClient::new().with_base_url(&base_url))
+        .with_round(Duration::from_millis(300));
+
+    let sui = TokenRef::new("SUI".to_string(), "0x2::sui::SUI".to_string());
+    let deep = TokenRef::new("DEEP".to_string(), "0xdee9::deep::DEEP".to_string());
+    let unknown = TokenRef::new("NOPE".to_string(), "0x3::nope::NOPE".to_string());
+
+    // Tokens asked for at once share a request
+    let (sui_quote, deep_quote) = tokio::join!(batcher.fetch(&sui), batcher.fetch(&deep));
+    assert_eq!(sui_quote.unwrap().symbol, "SUI");
+    assert_eq!(deep_quote.unwrap().price, 0.2);
+    let first_round = requests.load(Ordering::SeqCst);
+    assert!(first_round <= 2);
+
+    // Later in the round, known tokens are served without a new request
+    assert_eq!(batcher.fetch(&sui).await.unwrap().price, 3.5);
+    assert_eq!(requests.load(Ordering::SeqCst), first_round);
+
+    // A missing coin fails on its own, without affecting the others
+    assert!(batcher.fetch(&unknown).await.is_err());
+    assert_eq!(batcher.fetch(&deep).await.unwrap().symbol, "DEEP");
+    assert_eq!(requests.load(Ordering::SeqCst), first_round + 1);
+
+    // A new round fetches every known token in a single request
+    tokio::time::sleep(Duration::from_millis(350)).await;
+    assert_eq!(batcher.fetch(&deep).await.unwrap().symbol, "DEEP");
+    assert_eq!(batcher.fetch(&sui).await.unwrap().symbol, "SUI");
+    assert_eq!(requests.load(Ordering::SeqCst), first_round + 2);
+}
+
+// Test that a token with its own shorter round is not served prices from an older round
+#[tokio::test]
+async fn test_batcher_caps_the_round_of_frequent_tokens() {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let base_url = stand_in_server(requests.clone()).await;
+    let batcher = BatchingApiClient::new(ApiClient::new().with_base_url(&base_url))
+        .with_round(Duration::from_millis(500))
+        .with_token_round("sui", Duration::from_millis(50));
+
+    let sui = TokenRef::new("SUI".to_string(), "0x2::sui::SUI".to_string());
+    let deep = TokenRef::new("DEEP".to_string(), "0xdee9::deep::DEEP".to_string());
+    let (sui_quote, deep_quote) = tokio::join!(batcher.fetch(&sui), batcher.fetch(&deep));
+    assert!(sui_quote.is_ok() && deep_quote.is_ok());
+    let first_round = requests.load(Ordering::SeqCst);
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(batcher.fetch(&deep).await.is_ok());
+    assert_eq!(requests.load(Ordering::SeqCst), first_round);
+    assert!(batcher.fetch(&sui).await.is_ok());
+    assert_eq!(requests.load(Ordering::SeqCst), first_round + 1);
+}
@@ -0,0 +1,375 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use suicrypto_oracle::{
+    application::client_manager::ClientManager,
+    config::TokenConfig,
+    domain::{
+        backoff::Backoff,
+        price_provider::{PriceProvider, PriceQuote, TokenRef},
+        shutdown::Shutdown,
+        websocket_handler::{ConnectionState, WebSocketHandler},
+        websocket_server::WebSocketServer,
+    },
+    infraestructure::address_cache::AddressCache,
+    AppError,
+};
+use tokio::sync::broadcast;
+
+#[derive(Debug)]
+struct FixedProvider;
+
+#[async_trait]
+impl PriceProvider for FixedProvider {
+    fn name(&self) -> &str {
+        "fixed"
+    }
+
+    async fn fetch(&self, token: &TokenRef) -> Result<PriceQuote, AppError> {
+        Ok(PriceQuote::new(token.name.clone(), 3.0, Utc::now()))
+    }
+}
+
+#[derive(Debug)]
+struct StalledProvider;
+
+#[async_trait]
+impl PriceProvider for StalledProvider {
+    fn name(&self) -> &str {
+        "stalled"
+    }
+
+    async fn fetch(&self, _token: &TokenRef) -> Result<PriceQuote, AppError> {
+        std::future::pending().await
+    }
+}
+
+// Test that the retry delay grows exponentially, stays jittered and is capped
+#[test]
+fn test_backoff_delay_is_bounded() {
+    let backoff = Backoff::new(Duration::from_millis(100), Duration::from_secs(1));
+
+    for _ in 0..20 {
+        let first = backoff.delay(1);
+        assert!(first >= Duration::from_millis(50) && first <= Duration::from_millis(100));
+
+        let third = backoff.delay(3);
+        assert!(third >= Duration::from_millis(200) && third <= Duration::from_millis(400));
+
+        let capped = backoff.delay(30);
+        assert!(capped >= Duration::from_millis(500) && capped <= Duration::from_secs(1));
+    }
+}
+
+// Test that a client keeps retrying until the server comes up, then registers
+#[tokio::test]
+async fn test_handler_reconnects_and_registers() {
+    let address = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .to_string();
+
+    let states = Arc::new(Mutex::new(Vec::new()));
+    let recorded = states.clone();
+    let (tx, _) = broadcast::channel(16);
+    let handler = WebSocketHandler::new(
+        TokenRef::new("SUI".to_string(), "0x2::sui::SUI".to_string()),
+        Arc::new(FixedProvider),
+        tx,
+    )
+    .with_server_host(&address)
+    .with_backoff(Backoff::new(
+        Duration::from_millis(50),
+        Duration::from_millis(200),
+    ))
+    .with_state_callback(move |state| recorded.lock().unwrap().push(state.clone()));
+    let client_task = tokio::spawn(async move { handler.run().await });
+
+    // Nothing is listening yet, so the client has to back off and retry
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(states
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|s| matches!(s, ConnectionState::Reconnecting { attempt: 2, .. })));
+
+    let server = WebSocketServer::new(&address).expect("Error creating server");
+    let registry = server.registry();
+    let server_task = tokio::spawn(async move { server.run().await });
+
+    // The server registers the client before the client receives its acknowledgement
+    let registered = tokio::time::timeout(Duration::from_secs(5), async {
+        while registry.clients("SUI").is_empty()
+            || states.lock().unwrap().last() != Some(&ConnectionState::Registered)
+        {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await;
+    assert!(registered.is_ok());
+
+    client_task.abort();
+    server_task.abort();
+}
+
+// Test that a shutdown closes the client instead of making it reconnect
+#[tokio::test]
+async fn test_handler_stops_on_shutdown() {
+    let address = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .to_string();
+
+    let shutdown = Shutdown::new();
+    let server = WebSocketServer::new(&address)
+        .expect("Error creating server")
+        .with_shutdown(shutdown.clone());
+    let registry = server.registry();
+    let server_task = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let states = Arc::new(Mutex::new(Vec::new()));
+    let recorded = states.clone();
+    let (tx, _) = broadcast::channel(16);
+    let handler = WebSocketHandler::new(
+        TokenRef::new("SUI".to_string(), "0x2::sui::SUI".to_string()),
+        Arc::new(FixedProvider),
+        tx,
+    )
+    .with_server_host(&address)
+    .with_shutdown(shutdown.clone())
+    .with_state_callback(move |state| recorded.lock().unwrap().push(state.clone()));
+    let client_task = tokio::spawn(async move { handler.run().await });
+
+    while registry.clients("SUI").is_empty() {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    shutdown.trigger();
+
+    let client = tokio::time::timeout(Duration::from_secs(5), client_task).await;
+    assert!(matches!(client, Ok(Ok(Ok(())))));
+    let server = tokio::time::timeout(Duration::from_secs(5), server_task).await;
+    assert!(matches!(server, Ok(Ok(Ok(())))));
+    assert_eq!(
+        states.lock().unwrap().last(),
+        Some(&ConnectionState::ShutDown)
+    );
+    assert!(registry.tokens().is_empty());
+}
+
+// Test that a shutdown is not held up by a price fetch that never completes
+#[tokio::test]
+async fn test_handler_stops_during_stalled_fetch() {
+    use suicrypto_oracle::domain::scheduler::Schedule;
+
+    let address = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .to_string();
+
+    let shutdown = Shutdown::new();
+    let server = WebSocketServer::new(&address)
+        .expect("Error creating server")
+        .with_default_schedule(Schedule::new(Duration::from_millis(50), Duration::ZERO))
+        .with_shutdown(shutdown.clone());
+    let registry = server.registry();
+    let server_task = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let (tx, _) = broadcast::channel(16);
+    let handler = WebSocketHandler::new(
+        TokenRef::new("SUI".to_string(), "0x2::sui::SUI".to_string()),
+        Arc::new(StalledProvider),
+        tx,
+    )
+    .with_server_host(&address)
+    .with_shutdown(shutdown.clone());
+    let client_task = tokio::spawn(async move { handler.run().await });
+
+    while registry.clients("SUI").is_empty() {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    // Let the server request a price the provider never delivers
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    shutdown.trigger();
+
+    let client = tokio::time::timeout(Duration::from_secs(5), client_task).await;
+    assert!(matches!(client, Ok(Ok(Ok(())))));
+    let server = tokio::time::timeout(Duration::from_secs(5), server_task).await;
+    assert!(matches!(server, Ok(Ok(Ok(())))));
+}
+
+// Test that tokens declaring their coin type or found in a warm address cache get a
+// client without a CoinGecko lookup
+#[tokio::test]
+async fn test_client_manager_skips_lookup_of_known_tokens() {
+    let address = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .to_string();
+
+    let shutdown = Shutdown::new();
+    let server = WebSocketServer::new(&address)
+        .expect("Error creating server")
+        .with_shutdown(shutdown.clone());
+    let registry = server.registry();
+    let server_task = tokio::spawn(async move { server.run().await });
+
+    let cache_path =
+        std::env::temp_dir().join(format!("address-cache-handler-{}.json", std::process::id()));
+    let mut cache = AddressCache::open(&cache_path, chrono::Duration::days(1));
+    cache.insert("CACHED", "0xdef::coin::CACHED");
+
+    let tokens: Vec<TokenConfig> = serde_json::from_str(
+        r#"["CACHED", {"symbol": "NOTLISTED", "coin_type": "0xabc::coin::NOTLISTED"}]"#,
+    )
+    .unwrap();
+    let (tx, _) = broadcast::channel(16);
+    let mut manager = ClientManager::with_provider(Arc::new(FixedProvider))
+        .with_server_host(&address)
+        .with_shutdown(shutdown.clone())
+        .with_address_cache(cache);
+    manager
+        .create_clients(tokens, tx)
+        .await
+        .expect("Error creating clients");
+    let clients_task = tokio::spawn(manager.run_clients());
+
+    for (token, contract_address) in [
+        ("NOTLISTED", "0xabc::coin::NOTLISTED"),
+        ("CACHED", "0xdef::coin::CACHED"),
+    ] {
+        let registered = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                if let Some(client) = registry.clients(token).pop() {
+                    break client;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .expect("Client was not registered");
+        assert_eq!(registered.registration.contract_address, contract_address);
+    }
+    std::fs::remove_file(&cache_path).ok();
+
+    shutdown.trigger();
+    clients_task.await.unwrap().unwrap();
+    server_task.await.unwrap().unwrap();
+}
+
+// Test that a client rejected because another one holds its token keeps retrying, and
+// registers once the token is free
+#[tokio::test]
+async fn test_handler_retries_rejected_registration() {
+    use suicrypto_oracle::domain::client_registry::ClientRegistration;
+
+    let address = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .to_string();
+
+    let server = WebSocketServer::new(&address).expect("Error creating server");
+    let registry = server.registry();
+    let squatter = registry
+        .register(ClientRegistration::new(
+            "SUI".to_string(),
+            "0x9::fake::SUI".to_string(),
+            "other".to_string(),
+            "test".to_string(),
+        ))
+        .unwrap();
+    let server_task = tokio::spawn(async move { server.run().await });
+
+    let states = Arc::new(Mutex::new(Vec::new()));
+    let recorded = states.clone();
+    let (tx, _) = broadcast::channel(16);
+    let handler = WebSocketHandler::new(
+        TokenRef::new("SUI".to_string(), "0x2::sui::SUI".to_string()),
+        Arc::new(FixedProvider),
+        tx,
+    )
+    .with_server_host(&address)
+    .with_backoff(Backoff::new(
+        Duration::from_millis(50),
+        Duration::from_millis(200),
+    ))
+    .with_state_callback(move |state| recorded.lock().unwrap().push(state.clone()));
+    let client_task = tokio::spawn(async move { handler.run().await });
+
+    let retried = tokio::time::timeout(Duration::from_secs(5), async {
+        while !states
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|s| matches!(s, ConnectionState::Reconnecting { attempt: 2, .. }))
+        {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await;
+    assert!(retried.is_ok());
+    assert!(!client_task.is_finished());
+
+    registry.unregister("SUI", "other", squatter);
+    let registered = tokio::time::timeout(Duration::from_secs(5), async {
+        while states.lock().unwrap().last() != Some(&ConnectionState::Registered) {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await;
+    assert!(registered.is_ok());
+    assert_eq!(
+        registry.clients("SUI")[0].registration.contract_address,
+        "0x2::sui::SUI"
+    );
+
+    client_task.abort();
+    server_task.abort();
+}
+
+// Test that clients all refused by the server report an error instead of a clean stop
+#[tokio::test]
+async fn test_client_manager_fails_when_all_clients_stop() {
+    use futures_util::{SinkExt, StreamExt};
+    use suicrypto_oracle::domain::protocol::{encode, ErrorCode, ProtocolMessage};
+    use tokio_tungstenite::tungstenite::Message;
+
+    // Stand-in for a server speaking another protocol version
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let server_task = tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+            socket.next().await;
+            let refusal =
+                ProtocolMessage::error(ErrorCode::UnsupportedVersion, "Unsupported version");
+            socket
+                .send(Message::Text(encode(&refusal).unwrap()))
+                .await
+                .unwrap();
+        }
+    });
+
+    let tokens: Vec<TokenConfig> =
+        serde_json::from_str(r#"[{"symbol": "SUI", "coin_type": "0x2::sui::SUI"}]"#).unwrap();
+    let (tx, _) = broadcast::channel(16);
+    let mut manager =
+        ClientManager::with_provider(Arc::new(FixedProvider)).with_server_host(&address);
+    manager
+        .create_clients(tokens, tx)
+        .await
+        .expect("Error creating clients");
+
+    let result = tokio::time::timeout(Duration::from_secs(10), manager.run_clients()).await;
+    assert!(matches!(result, Ok(Err(AppError::ProtocolError(_)))));
+
+    server_task.abort();
+}
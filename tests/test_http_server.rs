@@ -0,0 +1,125 @@
+use chrono::Utc;
+use serde_json::Value;
+use suicrypto_oracle::domain::{
+    http_server::HttpServer,
+    price_provider::PriceQuote,
+    price_store::{PriceReport, PriceStore},
+    quote_policy::{FlagReason, QuoteFlag},
+};
+
+/// Returns a local address with a port that is currently free.
+fn free_address() -> String {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("Error binding to port");
+    listener.local_addr().unwrap().to_string()
+}
+
+// Test that the REST API serves the latest prices held by the store
+#[tokio::test]
+async fn test_http_server_serves_prices() {
+    let store = PriceStore::new();
+    store.update(
+        PriceReport {
+            contract_address: "0x2::sui::SUI".to_string(),
+            quote: PriceQuote::new("SUI".to_string(), 3.5, Utc::now()),
+            signature: None,
+        },
+        Utc::now(),
+    );
+    store.exclude(
+        PriceReport::new(
+            "0xabc::wal::WAL".to_string(),
+            PriceQuote::new("WAL".to_string(), 0.5, Utc::now()),
+        ),
+        vec![QuoteFlag {
+            reason: FlagReason::Stale,
+            message: "Quote of WAL is 400s old, more than 300s".to_string(),
+        }],
+    );
+
+    let address = free_address();
+    let server = HttpServer::new(&address, store);
+    let server_task = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+
+    let prices: Value = reqwest::get(format!("http://{}/prices", address))
+        .await
+        .expect("Error calling /prices")
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(prices.as_array().map(Vec::len), Some(1));
+
+    let sui: Value = reqwest::get(format!("http://{}/prices/sui", address))
+        .await
+        .expect("Error calling /prices/sui")
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(sui["symbol"], "SUI");
+    assert_eq!(sui["price"], 3.5);
+    assert!(sui["age_secs"].is_i64());
+
+    let missing = reqwest::get(format!("http://{}/prices/deep", address))
+        .await
+        .expect("Error calling /prices/deep");
+    assert_eq!(missing.status(), reqwest::StatusCode::NOT_FOUND);
+
+    // Tokens whose reports were all excluded show why they have no price
+    let unpublished = reqwest::get(format!("http://{}/prices/wal", address))
+        .await
+        .expect("Error calling /prices/wal");
+    assert_eq!(unpublished.status(), reqwest::StatusCode::NOT_FOUND);
+    let body: Value = unpublished.json().await.unwrap();
+    assert_eq!(body["excluded"]["flags"][0]["reason"], "stale");
+
+    server_task.abort();
+}
+
+// Test that the history endpoint serves the stored prices of a token
+#[tokio::test]
+async fn test_http_server_serves_history() {
+    use suicrypto_oracle::infraestructure::price_history::PriceHistory;
+
+    let store = PriceStore::new();
+    let history = PriceHistory::open_in_memory(None).expect("Error opening history");
+    for price in [3.0, 3.2] {
+        let record = store
+            .update(
+                PriceReport::new(
+                    "0x2::sui::SUI".to_string(),
+                    PriceQuote::new("SUI".to_string(), price, Utc::now()),
+                ),
+                Utc::now(),
+            )
+            .unwrap();
+        history.append(&record, true).unwrap();
+    }
+
+    let address = free_address();
+    let server = HttpServer::new(&address, store).with_history(history);
+    let server_task = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+
+    let body: Value = reqwest::get(format!("http://{}/prices/sui/history?interval=1d", address))
+        .await
+        .expect("Error calling /prices/sui/history")
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["symbol"], "SUI");
+    assert_eq!(body["points"][0]["count"], 2);
+    assert_eq!(body["points"][0]["last"], 3.2);
+
+    // Intervals too long to represent are rejected instead of crashing the handler
+    for interval in ["9999999999999999s", "9999999999999d", "0h", "1w"] {
+        let response = reqwest::get(format!(
+            "http://{}/prices/sui/history?interval={}",
+            address, interval
+        ))
+        .await
+        .expect("Error calling /prices/sui/history");
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    }
+
+    server_task.abort();
+}
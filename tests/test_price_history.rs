@@ -0,0 +1,113 @@
+use chrono::{Duration, Utc};
+use suicrypto_oracle::{
+    domain::price_store::PriceRecord, infraestructure::price_history::PriceHistory,
+};
+
+fn record(symbol: &str, age: Duration) -> PriceRecord {
+    let received_at = Utc::now() - age;
+    PriceRecord {
+        symbol: symbol.to_string(),
+        contract_address: "0x2::sui::SUI".to_string(),
+        price: 3.0,
+        timestamp: received_at,
+        sources: 1,
+        received_at,
+        attestation: None,
+        excluded: None,
+    }
+}
+
+// Test that appended records are kept and pruned according to the retention policy
+#[test]
+fn test_history_append_and_prune() {
+    let history =
+        PriceHistory::open_in_memory(Some(Duration::days(7))).expect("Error opening history");
+
+    history
+        .append(&record("SUI", Duration::days(10)), true)
+        .unwrap();
+    history
+        .append(&record("SUI", Duration::hours(1)), true)
+        .unwrap();
+    history
+        .append(&record("DEEP", Duration::hours(1)), false)
+        .unwrap();
+    assert_eq!(history.count("sui").unwrap(), 2);
+
+    assert_eq!(history.prune().unwrap(), 1);
+    assert_eq!(history.count("SUI").unwrap(), 1);
+    assert_eq!(history.count("DEEP").unwrap(), 1);
+}
+
+// Test that price points are grouped into epoch-aligned buckets with last and average prices
+#[test]
+fn test_history_range_downsampling() {
+    use chrono::TimeZone;
+    use suicrypto_oracle::infraestructure::price_history::downsample;
+
+    let history = PriceHistory::open_in_memory(None).expect("Error opening history");
+    let base = Utc.timestamp_opt(1732003200, 0).unwrap(); // aligned to the hour
+    for (offset, price) in [(0, 1.0), (600, 3.0), (3600, 5.0), (7300, 7.0)] {
+        let mut point = record("SUI", Duration::zero());
+        point.timestamp = base + Duration::seconds(offset);
+        point.price = price;
+        history.append(&point, true).unwrap();
+    }
+
+    let points = history
+        .range("SUI", base, base + Duration::seconds(3600))
+        .unwrap();
+    assert_eq!(points.len(), 3);
+
+    let buckets = downsample(&points, Duration::hours(1));
+    assert_eq!(buckets.len(), 2);
+    assert_eq!(buckets[0].start, base);
+    assert_eq!(buckets[0].count, 2);
+    assert_eq!(buckets[0].last, 3.0);
+    assert_eq!(buckets[0].avg, 2.0);
+    assert_eq!(buckets[1].last, 5.0);
+}
+
+// Test that a database created before held-back reports were kept is upgraded in place,
+// its prices counting as published
+#[test]
+fn test_history_upgrades_old_databases() {
+    let path = std::env::temp_dir().join(format!("history-upgrade-{}.db", std::process::id()));
+    let path = path.to_str().unwrap();
+    std::fs::remove_file(path).ok();
+    {
+        let conn = rusqlite::Connection::open(path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE price_history (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                symbol TEXT NOT NULL,
+                contract_address TEXT NOT NULL,
+                price REAL NOT NULL,
+                sources INTEGER NOT NULL,
+                source_timestamp INTEGER NOT NULL,
+                received_at INTEGER NOT NULL
+            );",
+        )
+        .unwrap();
+        let millis = Utc::now().timestamp_millis();
+        conn.execute(
+            "INSERT INTO price_history
+                (symbol, contract_address, price, sources, source_timestamp, received_at)
+             VALUES ('SUI', '0x2::sui::SUI', 3.0, 1, ?1, ?1)",
+            [millis],
+        )
+        .unwrap();
+    }
+
+    let history = PriceHistory::open(path, None).expect("Error opening history");
+    history
+        .append(&record("SUI", Duration::zero()), false)
+        .unwrap();
+    let points = history
+        .range("SUI", Utc::now() - Duration::hours(1), Utc::now())
+        .unwrap();
+    std::fs::remove_file(path).ok();
+
+    let published: Vec<bool> = points.iter().map(|p| p.published).collect();
+    assert_eq!(published, vec![true, false]);
+}
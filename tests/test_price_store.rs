@@ -0,0 +1,155 @@
+use chrono::{TimeZone, Utc};
+use std::time::Duration;
+use suicrypto_oracle::domain::{
+    price_provider::PriceQuote,
+    price_store::{PriceReport, PriceStore},
+    publish_policy::PublishPolicy,
+    quote_policy::{FlagReason, QuoteFlag, QuotePolicy},
+};
+
+fn quote(symbol: &str, price: f64, timestamp: i64) -> PriceReport {
+    PriceReport {
+        contract_address: format!("0x2::{}::{}", symbol.to_lowercase(), symbol),
+        quote: PriceQuote::new(
+            symbol.to_string(),
+            price,
+            Utc.timestamp_opt(timestamp, 0).unwrap(),
+        ),
+        signature: None,
+    }
+}
+
+// Test that the store keeps the latest quote per symbol and ignores older reports
+#[test]
+fn test_store_keeps_latest_quote() {
+    let store = PriceStore::new();
+
+    assert!(store
+        .update(quote("sui", 3.0, 1732000000), Utc::now())
+        .is_some());
+    assert!(store
+        .update(quote("SUI", 3.1, 1732000010), Utc::now())
+        .is_some());
+    assert!(store
+        .update(quote("SUI", 2.9, 1731999990), Utc::now())
+        .is_none());
+    assert!(store
+        .update(quote("DEEP", 0.2, 1732000000), Utc::now())
+        .is_some());
+
+    let sui = store.get("sui").expect("SUI price not stored");
+    assert_eq!(sui.price, 3.1);
+    assert_eq!(sui.timestamp.timestamp(), 1732000010);
+
+    let symbols: Vec<String> = store.all().into_iter().map(|r| r.symbol).collect();
+    assert_eq!(symbols, vec!["DEEP", "SUI"]);
+}
+
+fn stale_deep(now: i64) -> PriceReport {
+    let mut report = quote("DEEP", 0.1, now);
+    report.quote.flags = vec![QuoteFlag {
+        reason: FlagReason::Stale,
+        message: "defillama: quote of DEEP is 600s old".to_string(),
+    }];
+    report
+}
+
+// Test that reports failing the quote policy are kept out of the published price
+#[test]
+fn test_store_excludes_flagged_reports() {
+    let store = PriceStore::new()
+        .with_token_quote_policy("SUI", QuotePolicy::new(Some(Duration::from_secs(60)), None));
+    let now = Utc::now().timestamp();
+    let mut updates = store.subscribe();
+
+    let fresh = quote("SUI", 3.0, now - 10);
+    assert!(store.check(&fresh).is_empty());
+    store.update(fresh, Utc::now()).unwrap();
+
+    // Stale reports are flagged, and so are the reports flagged by their client
+    let stale = quote("SUI", 2.0, now - 5);
+    let stale = PriceReport {
+        quote: PriceQuote {
+            timestamp: Utc.timestamp_opt(now - 600, 0).unwrap(),
+            ..stale.quote
+        },
+        ..stale
+    };
+    let flags = store.check(&stale);
+    assert_eq!(flags[0].reason, FlagReason::Stale);
+    assert!(store.exclude(stale, flags).is_none());
+
+    let mut low_confidence = quote("SUI", 2.5, now);
+    low_confidence.quote.flags = vec![QuoteFlag {
+        reason: FlagReason::LowConfidence,
+        message: "defillama: confidence 0.5".to_string(),
+    }];
+    let flags = store.check(&low_confidence);
+    let record = store.exclude(low_confidence, flags).unwrap();
+
+    assert_eq!(record.price, 3.0);
+    let excluded = record.excluded.expect("Excluded report not shown");
+    assert_eq!(excluded.price, 2.5);
+    assert_eq!(excluded.flags[0].reason, FlagReason::LowConfidence);
+    assert_eq!(updates.try_recv().unwrap().excluded, None);
+    assert!(updates.try_recv().unwrap().excluded.is_some());
+
+    // A published price clears the excluded report
+    store
+        .update(quote("SUI", 3.1, now + 1), Utc::now())
+        .unwrap();
+    assert_eq!(store.get("SUI").unwrap().excluded, None);
+
+    // Symbols without a published price keep their excluded report until one is published
+    let flags = store.check(&stale_deep(now));
+    assert!(store.exclude(stale_deep(now), flags).is_none());
+    let unpublished = store.unpublished("deep").expect("Excluded report not kept");
+    assert_eq!(unpublished.excluded.flags[0].reason, FlagReason::Stale);
+    assert_eq!(store.all_unpublished().len(), 1);
+    store.update(quote("DEEP", 0.2, now), Utc::now()).unwrap();
+    assert!(store.unpublished("DEEP").is_none());
+    assert!(store.check(&quote("DEEP", 0.2, now - 600)).is_empty());
+}
+
+// Test that reports are published only past the deviation threshold or the heartbeat
+#[test]
+fn test_store_applies_publish_policy() {
+    let store = PriceStore::new().with_token_publish_policy(
+        "SUI",
+        PublishPolicy::new(Some(100), Some(Duration::from_secs(3600))),
+    );
+    let mut updates = store.subscribe();
+    let at = |secs: i64| Utc.timestamp_opt(1732000000 + secs, 0).unwrap();
+
+    // The first price is always published
+    assert!(store.update(quote("SUI", 3.0, 1732000000), at(0)).is_some());
+    assert!(updates.try_recv().is_ok());
+
+    // Moves within 1% are held back, larger ones are published
+    assert!(store
+        .update(quote("SUI", 3.02, 1732000010), at(10))
+        .is_none());
+    assert!(updates.try_recv().is_err());
+    assert_eq!(store.get("SUI").unwrap().price, 3.0);
+    assert!(store
+        .update(quote("SUI", 2.9, 1732000020), at(20))
+        .is_some());
+    assert_eq!(updates.try_recv().unwrap().price, 2.9);
+
+    // Once the heartbeat elapses, the next report is published however little it moved
+    assert!(store
+        .update(quote("SUI", 2.9, 1732000030), at(3619))
+        .is_none());
+    assert!(store
+        .update(quote("SUI", 2.9, 1732000040), at(3620))
+        .is_some());
+    assert_eq!(store.get("SUI").unwrap().timestamp.timestamp(), 1732000040);
+
+    // Tokens without a policy publish every report
+    assert!(store
+        .update(quote("DEEP", 0.2, 1732000000), at(0))
+        .is_some());
+    assert!(store
+        .update(quote("DEEP", 0.2, 1732000010), at(1))
+        .is_some());
+}
@@ -0,0 +1,42 @@
+use chrono::{TimeZone, Utc};
+use suicrypto_oracle::{
+    domain::{
+        price_provider::PriceQuote,
+        price_store::PriceReport,
+        protocol::{decode, encode, error_code, ErrorCode, ProtocolMessage},
+    },
+    AppError,
+};
+
+// Test that a price report survives an encode/decode round trip
+#[test]
+fn test_protocol_round_trip() {
+    let message = ProtocolMessage::PriceReport(PriceReport {
+        contract_address: "0x2::sui::SUI".to_string(),
+        quote: PriceQuote::new(
+            "SUI".to_string(),
+            3.5,
+            Utc.timestamp_opt(1732000000, 0).unwrap(),
+        ),
+        signature: None,
+    });
+
+    let text = encode(&message).expect("Error encoding message");
+    assert!(text.contains(r#""version":1"#));
+    assert!(text.contains(r#""type":"price_report""#));
+
+    assert_eq!(decode(&text).expect("Error decoding message"), message);
+}
+
+// Test that malformed and incompatible messages are detected
+#[test]
+fn test_protocol_rejects_invalid_messages() {
+    let legacy = decode("REQUEST_TOKEN_PRICE").unwrap_err();
+    assert_eq!(error_code(&legacy), ErrorCode::MalformedMessage);
+
+    let unknown = decode(r#"{"version": 1, "type": "teleport"}"#).unwrap_err();
+    assert!(matches!(unknown, AppError::ProtocolError(_)));
+
+    let future = decode(r#"{"version": 99, "type": "price_request"}"#).unwrap_err();
+    assert_eq!(error_code(&future), ErrorCode::UnsupportedVersion);
+}
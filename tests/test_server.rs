@@ -1,8 +1,87 @@
+use chrono::Utc;
+use futures_util::{SinkExt, StreamExt};
+use suicrypto_oracle::domain::{
+    client_registry::ClientRegistration,
+    price_provider::PriceQuote,
+    price_store::PriceReport,
+    protocol::{decode, encode, ErrorCode, ProtocolMessage},
+    scheduler::Schedule,
+    websocket_server::WebSocketServer,
+};
+use tokio::net::TcpStream;
+use tokio_tungstenite::{
+    connect_async, tungstenite::protocol::Message, MaybeTlsStream, WebSocketStream,
+};
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Starts a server on a free local port and returns it with its WebSocket URL.
+async fn start_server() -> (WebSocketServer, String) {
+    let address = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .to_string();
+    let server = WebSocketServer::new(&address).expect("Error creating server");
+    (server, format!("ws://{}", address))
+}
+
+fn report(symbol: &str, price: f64) -> PriceReport {
+    PriceReport {
+        contract_address: format!("0x2::{}::{}", symbol.to_lowercase(), symbol),
+        quote: PriceQuote::new(symbol.to_string(), price, Utc::now()),
+        signature: None,
+    }
+}
+
+async fn send(socket: &mut Socket, message: ProtocolMessage) {
+    let text = encode(&message).unwrap();
+    socket.send(Message::Text(text)).await.unwrap();
+}
+
+/// Waits for the next protocol message, skipping control frames.
+async fn receive(socket: &mut Socket) -> ProtocolMessage {
+    loop {
+        match socket.next().await {
+            Some(Ok(Message::Text(text))) => return decode(&text).unwrap(),
+            Some(Ok(_)) => continue,
+            other => panic!("Unexpected message: {:?}", other),
+        }
+    }
+}
+
+/// Returns the registration of a price-reporting client for `symbol`.
+fn registration(symbol: &str) -> ClientRegistration {
+    ClientRegistration::new(
+        symbol.to_string(),
+        report(symbol, 0.0).contract_address,
+        format!("{}-test", symbol.to_lowercase()),
+        "test".to_string(),
+    )
+}
+
+/// Connects a price-reporting client and registers it for `symbol`.
+async fn connect_reporter(url: &str, symbol: &str) -> Socket {
+    register(url, registration(symbol)).await
+}
+
+/// Connects a price-reporting client and sends the given registration, which must be accepted.
+async fn register(url: &str, registration: ClientRegistration) -> Socket {
+    let (mut socket, _) = connect_async(url).await.expect("Error connecting client");
+    send(&mut socket, ProtocolMessage::Register(registration)).await;
+    assert_eq!(
+        receive(&mut socket).await,
+        ProtocolMessage::Ack {
+            of: "register".to_string()
+        }
+    );
+    socket
+}
+
 /// Test if the server can start and listen on a dynamic port (0).
 /// This ensures that the server binds to an available port.
 #[tokio::test]
 async fn test_server_can_start() {
-    use suicrypto_oracle::domain::websocket_server::WebSocketServer;
     use tokio::net::TcpListener;
 
     // Use a dynamic port (0 will automatically select an available port)
@@ -29,3 +108,415 @@ async fn test_server_can_start() {
 
     server_task.abort();
 }
+
+/// Test that a consumer receives a snapshot on subscription and then only
+/// the updates of the symbols it subscribed to.
+#[tokio::test]
+async fn test_consumer_subscription() {
+    let (server, url) = start_server().await;
+    server.store().update(report("SUI", 3.0), Utc::now());
+    let server_task = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+
+    let (mut consumer, _) = connect_async(&url)
+        .await
+        .expect("Error connecting consumer");
+    consumer
+        .send(Message::Text(
+            r#"{"version": 1, "type": "subscribe", "symbols": ["sui"]}"#.to_string(),
+        ))
+        .await
+        .unwrap();
+
+    match receive(&mut consumer).await {
+        ProtocolMessage::Snapshot { prices, .. } => {
+            assert_eq!(prices.len(), 1);
+            assert_eq!(prices[0].symbol, "SUI");
+            assert_eq!(prices[0].price, 3.0);
+        }
+        other => panic!("Unexpected message: {:?}", other),
+    }
+
+    // Reporting clients send prices for an unsubscribed and a subscribed symbol
+    for (symbol, price) in [("DEEP", 0.2), ("SUI", 3.2)] {
+        let mut reporter = connect_reporter(&url, symbol).await;
+        send(
+            &mut reporter,
+            ProtocolMessage::PriceReport(report(symbol, price)),
+        )
+        .await;
+        receive(&mut reporter).await;
+    }
+
+    match receive(&mut consumer).await {
+        ProtocolMessage::PriceUpdate(record) => {
+            assert_eq!(record.symbol, "SUI");
+            assert_eq!(record.price, 3.2);
+        }
+        other => panic!("Unexpected message: {:?}", other),
+    }
+
+    server_task.abort();
+}
+
+/// Test that reports are only accepted from clients registered for the reported token,
+/// and that clients are unregistered when they disconnect.
+#[tokio::test]
+async fn test_reports_require_registration() {
+    let (server, url) = start_server().await;
+    let store = server.store();
+    let registry = server.registry();
+    let server_task = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+
+    // Unregistered client
+    let (mut anonymous, _) = connect_async(&url).await.unwrap();
+    send(
+        &mut anonymous,
+        ProtocolMessage::PriceReport(report("SUI", 3.0)),
+    )
+    .await;
+    assert!(matches!(
+        receive(&mut anonymous).await,
+        ProtocolMessage::Error {
+            code: ErrorCode::Rejected,
+            ..
+        }
+    ));
+
+    // Client registered for another token
+    let mut reporter = connect_reporter(&url, "DEEP").await;
+    assert_eq!(registry.tokens(), vec!["DEEP"]);
+    send(
+        &mut reporter,
+        ProtocolMessage::PriceReport(report("SUI", 3.0)),
+    )
+    .await;
+    assert!(matches!(
+        receive(&mut reporter).await,
+        ProtocolMessage::Error {
+            code: ErrorCode::Rejected,
+            ..
+        }
+    ));
+    assert!(store.get("SUI").is_none());
+
+    send(
+        &mut reporter,
+        ProtocolMessage::PriceReport(report("DEEP", 0.2)),
+    )
+    .await;
+    assert!(matches!(
+        receive(&mut reporter).await,
+        ProtocolMessage::Ack { .. }
+    ));
+    assert_eq!(store.get("DEEP").map(|r| r.price), Some(0.2));
+
+    // The registered token's address under another symbol is still published as the token
+    let mut renamed = report("DEEP", 0.3);
+    renamed.quote.symbol = "SUI".to_string();
+    send(&mut reporter, ProtocolMessage::PriceReport(renamed)).await;
+    receive(&mut reporter).await;
+    assert!(store.get("SUI").is_none());
+    assert_eq!(store.get("DEEP").map(|r| r.price), Some(0.3));
+
+    reporter.close(None).await.unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    assert!(registry.tokens().is_empty());
+
+    server_task.abort();
+}
+
+/// Test that a client reconnecting before its old connection closes stays registered.
+#[tokio::test]
+async fn test_reconnected_client_stays_registered() {
+    let (server, url) = start_server().await;
+    let registry = server.registry();
+    let server_task = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+
+    let mut old = connect_reporter(&url, "SUI").await;
+    let _new = connect_reporter(&url, "SUI").await;
+    old.close(None).await.unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+
+    assert_eq!(registry.tokens(), vec!["SUI"]);
+    assert_eq!(registry.clients("SUI").len(), 1);
+
+    server_task.abort();
+}
+
+/// Test that price requests only reach the clients they are addressed to.
+#[tokio::test]
+async fn test_targeted_price_requests() {
+    let (server, url) = start_server().await;
+    let server = server.with_default_schedule(Schedule::new(
+        std::time::Duration::from_secs(3600),
+        std::time::Duration::ZERO,
+    ));
+    let requester = server.requester();
+    let server_task = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+
+    let mut sui = connect_reporter(&url, "SUI").await;
+    let mut deep = connect_reporter(&url, "DEEP").await;
+
+    requester
+        .request_token("sui")
+        .expect("Error requesting SUI");
+    assert_eq!(receive(&mut sui).await, ProtocolMessage::PriceRequest);
+
+    requester
+        .request_client("DEEP", "deep-test")
+        .expect("Error requesting DEEP client");
+    assert_eq!(receive(&mut deep).await, ProtocolMessage::PriceRequest);
+
+    // SUI must not have received the request addressed to the DEEP client
+    let pending =
+        tokio::time::timeout(std::time::Duration::from_millis(200), receive(&mut sui)).await;
+    assert!(pending.is_err());
+
+    assert!(requester.request_token("WAL").is_err());
+
+    server_task.abort();
+}
+
+/// Test that each token is polled on its own schedule.
+#[tokio::test]
+async fn test_per_token_schedules() {
+    use std::time::Duration;
+
+    let (server, url) = start_server().await;
+    let server = server
+        .with_default_schedule(Schedule::new(Duration::from_secs(3600), Duration::ZERO))
+        .with_token_schedule(
+            "sui",
+            Schedule::new(Duration::from_millis(100), Duration::ZERO),
+        );
+    let server_task = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let mut sui = connect_reporter(&url, "SUI").await;
+    let mut deep = connect_reporter(&url, "DEEP").await;
+
+    for _ in 0..3 {
+        let request = tokio::time::timeout(Duration::from_secs(2), receive(&mut sui)).await;
+        assert_eq!(request.ok(), Some(ProtocolMessage::PriceRequest));
+    }
+
+    // DEEP follows the hourly default and should not have been polled yet
+    let pending = tokio::time::timeout(Duration::from_millis(300), receive(&mut deep)).await;
+    assert!(pending.is_err());
+
+    server_task.abort();
+}
+
+/// Test that clients answering pings stay registered while silent ones are dropped.
+#[tokio::test]
+async fn test_silent_clients_are_dropped() {
+    use std::time::Duration;
+    use suicrypto_oracle::domain::heartbeat::Heartbeat;
+
+    let (server, url) = start_server().await;
+    let server = server.with_heartbeat(Heartbeat::new(
+        Duration::from_millis(100),
+        Duration::from_millis(300),
+    ));
+    let registry = server.registry();
+    let server_task = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // SUI never reads again, so its pongs are never sent
+    let _sui = connect_reporter(&url, "SUI").await;
+    let mut deep = connect_reporter(&url, "DEEP").await;
+    let deep_task = tokio::spawn(async move { while deep.next().await.is_some() {} });
+
+    tokio::time::sleep(Duration::from_millis(800)).await;
+    assert_eq!(registry.tokens(), vec!["DEEP".to_string()]);
+
+    deep_task.abort();
+    server_task.abort();
+}
+
+/// Test that peers stalling before the WebSocket handshake completes are dropped.
+#[tokio::test]
+async fn test_stalled_handshakes_are_dropped() {
+    use std::time::Duration;
+    use suicrypto_oracle::domain::heartbeat::Heartbeat;
+    use tokio::io::AsyncReadExt;
+
+    let (server, url) = start_server().await;
+    let server = server.with_heartbeat(Heartbeat::new(
+        Duration::from_millis(100),
+        Duration::from_millis(300),
+    ));
+    let server_task = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let mut stalled = TcpStream::connect(url.trim_start_matches("ws://"))
+        .await
+        .unwrap();
+    let mut buffer = [0; 16];
+    let closed = tokio::time::timeout(Duration::from_secs(2), stalled.read(&mut buffer)).await;
+    assert!(matches!(closed, Ok(Ok(0)) | Ok(Err(_))));
+
+    server_task.abort();
+}
+
+/// Test that shutdown closes clients with a close frame after recording their last report.
+#[tokio::test]
+async fn test_graceful_shutdown() {
+    use std::time::Duration;
+    use suicrypto_oracle::domain::shutdown::Shutdown;
+    use suicrypto_oracle::infraestructure::price_history::PriceHistory;
+    use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+
+    let shutdown = Shutdown::new();
+    let history = PriceHistory::open_in_memory(None).unwrap();
+    let (server, url) = start_server().await;
+    let server = server
+        .with_shutdown(shutdown.clone())
+        .with_history(history.clone());
+    let store = server.store();
+    let server_task = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let mut socket = connect_reporter(&url, "SUI").await;
+    send(
+        &mut socket,
+        ProtocolMessage::PriceReport(report("SUI", 3.5)),
+    )
+    .await;
+    shutdown.trigger();
+
+    // The report is acknowledged or drained, then the server says goodbye
+    let close = loop {
+        match socket.next().await {
+            Some(Ok(Message::Close(frame))) => break frame,
+            Some(Ok(_)) => continue,
+            other => panic!("Unexpected message: {:?}", other),
+        }
+    };
+    assert_eq!(close.map(|f| f.code), Some(CloseCode::Away));
+
+    let stopped = tokio::time::timeout(Duration::from_secs(5), server_task).await;
+    assert!(matches!(stopped, Ok(Ok(Ok(())))));
+    assert_eq!(store.get("SUI").map(|r| r.price), Some(3.5));
+    // The report reached the history before the server stopped
+    assert_eq!(history.count("SUI").unwrap(), 1);
+}
+
+/// Test that only registrations and reports signed by an allowlisted key are accepted
+/// when signing is enforced.
+#[tokio::test]
+async fn test_signed_reports_are_enforced() {
+    use suicrypto_oracle::domain::signing::{ReportSigner, ReportVerifier};
+
+    let signer = ReportSigner::generate();
+    let stranger = ReportSigner::generate();
+    let verifier = ReportVerifier::new(&[signer.public_key()]).unwrap();
+
+    let (server, url) = start_server().await;
+    let server = server.with_report_verifier(verifier);
+    let store = server.store();
+    let server_task = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+
+    // An unknown peer cannot claim the token, whatever contract address it gives
+    let mut bogus = registration("SUI");
+    bogus.contract_address = "0x9::fake::SUI".to_string();
+    for unauthorized in [bogus.clone(), stranger.sign_registration(bogus)] {
+        let (mut socket, _) = connect_async(&url).await.expect("Error connecting client");
+        send(&mut socket, ProtocolMessage::Register(unauthorized)).await;
+        assert!(matches!(
+            receive(&mut socket).await,
+            ProtocolMessage::Error {
+                code: ErrorCode::Rejected,
+                ..
+            }
+        ));
+    }
+
+    let mut socket = register(&url, signer.sign_registration(registration("SUI"))).await;
+    for unauthorized in [report("SUI", 1.0), stranger.sign(report("SUI", 2.0))] {
+        send(&mut socket, ProtocolMessage::PriceReport(unauthorized)).await;
+        assert!(matches!(
+            receive(&mut socket).await,
+            ProtocolMessage::Error {
+                code: ErrorCode::Rejected,
+                ..
+            }
+        ));
+    }
+    assert!(store.get("SUI").is_none());
+
+    send(
+        &mut socket,
+        ProtocolMessage::PriceReport(signer.sign(report("SUI", 3.5))),
+    )
+    .await;
+    assert_eq!(
+        receive(&mut socket).await,
+        ProtocolMessage::Ack {
+            of: "price_report".to_string()
+        }
+    );
+    assert_eq!(store.get("SUI").map(|r| r.price), Some(3.5));
+
+    server_task.abort();
+}
+
+/// Test that reports held back by the publishing policy are kept in the history, but
+/// neither published nor attested.
+#[tokio::test]
+async fn test_held_back_reports_are_kept_in_history() {
+    use std::sync::Arc;
+    use std::time::Duration;
+    use suicrypto_oracle::domain::{
+        price_store::PriceStore, publish_policy::PublishPolicy, signing::AttestationSigner,
+    };
+    use suicrypto_oracle::infraestructure::price_history::PriceHistory;
+
+    let address = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .to_string();
+    let store = PriceStore::new()
+        .with_attestor(Arc::new(AttestationSigner::generate()))
+        .with_publish_policy(PublishPolicy::new(Some(100), None));
+    let history = PriceHistory::open_in_memory(None).unwrap();
+    let server = WebSocketServer::from_store(&address, store.clone())
+        .expect("Error creating server")
+        .with_history(history.clone());
+    let server_task = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let mut socket = connect_reporter(&format!("ws://{}", address), "SUI").await;
+    for price in [3.0, 3.01] {
+        send(
+            &mut socket,
+            ProtocolMessage::PriceReport(report("SUI", price)),
+        )
+        .await;
+        assert_eq!(
+            receive(&mut socket).await,
+            ProtocolMessage::Ack {
+                of: "price_report".to_string()
+            }
+        );
+    }
+
+    let published = store.get("SUI").unwrap();
+    assert_eq!(published.price, 3.0);
+    assert_eq!(published.attestation.unwrap().round_id, 1);
+
+    let points = history
+        .range("SUI", Utc::now() - chrono::Duration::hours(1), Utc::now())
+        .unwrap();
+    let stored: Vec<(f64, bool)> = points.iter().map(|p| (p.price, p.published)).collect();
+    assert_eq!(stored, vec![(3.0, true), (3.01, false)]);
+    assert_eq!(history.last_rounds().unwrap().get("SUI"), Some(&1));
+
+    server_task.abort();
+}
@@ -0,0 +1,170 @@
+use chrono::Utc;
+use std::sync::Arc;
+use suicrypto_oracle::domain::{
+    price_provider::PriceQuote,
+    price_store::{PriceRecord, PriceReport, PriceStore},
+    protocol::{decode, encode, ProtocolMessage},
+    quote_policy::{FlagReason, QuoteFlag},
+    signing::{verify_attestation, AttestationSigner, ReportSigner, ReportVerifier},
+};
+use suicrypto_oracle::infraestructure::price_history::PriceHistory;
+
+fn report(price: f64) -> PriceReport {
+    PriceReport::new(
+        "0x2::sui::SUI".to_string(),
+        PriceQuote::new("SUI".to_string(), price, Utc::now()),
+    )
+}
+
+// Test that a signed report survives the wire and verifies against the allowlist
+#[test]
+fn test_signed_report_round_trip() {
+    let signer = ReportSigner::generate();
+    let verifier = ReportVerifier::new(&[signer.public_key()]).unwrap();
+
+    let message = ProtocolMessage::PriceReport(signer.sign(report(1.2345678901234567)));
+    let text = encode(&message).unwrap();
+    assert!(text.contains(r#""public_key""#));
+
+    let ProtocolMessage::PriceReport(received) = decode(&text).unwrap() else {
+        panic!("Expected a price report");
+    };
+    assert!(verifier.verify(&received).is_ok());
+}
+
+// Test that tampered, replayed and unsigned reports are rejected
+#[test]
+fn test_invalid_reports_are_rejected() {
+    let signer = ReportSigner::generate();
+    let verifier = ReportVerifier::new(&[signer.public_key()]).unwrap();
+
+    let mut tampered = signer.sign(report(3.0));
+    tampered.quote.price = 30.0;
+    assert!(verifier.verify(&tampered).is_err());
+
+    // Sources, confidence and flags are signed too
+    let mut flagged = report(3.0);
+    flagged.quote.confidence = Some(0.5);
+    flagged.quote.flags = vec![QuoteFlag {
+        reason: FlagReason::LowConfidence,
+        message: "defillama: confidence 0.5".to_string(),
+    }];
+    let flagged = signer.sign(flagged);
+    let tamperings: [fn(&mut PriceReport); 3] = [
+        |r| r.quote.sources = 3,
+        |r| r.quote.confidence = Some(0.99),
+        |r| r.quote.flags.clear(),
+    ];
+    for tamper in tamperings {
+        let mut tampered = flagged.clone();
+        tamper(&mut tampered);
+        assert!(verifier.verify(&tampered).is_err());
+    }
+
+    let signed = signer.sign(report(3.0));
+    assert!(verifier.verify(&signed).is_ok());
+    assert!(verifier.verify(&signed).is_err());
+
+    assert!(verifier.verify(&report(3.0)).is_err());
+}
+
+// Test that the reports of different tokens signed by one key may arrive in any order
+#[test]
+fn test_nonces_are_tracked_per_token() {
+    let signer = ReportSigner::generate();
+    let verifier = ReportVerifier::new(&[signer.public_key()]).unwrap();
+
+    let sui = signer.sign(report(3.0));
+    let deep = signer.sign(PriceReport::new(
+        "0xdee9::deep::DEEP".to_string(),
+        PriceQuote::new("DEEP".to_string(), 0.2, Utc::now()),
+    ));
+    assert!(verifier.verify(&deep).is_ok());
+    assert!(verifier.verify(&sui).is_ok());
+    assert!(verifier.verify(&sui).is_err());
+}
+
+// Test that a report captured before a restart is rejected once it is no longer fresh
+#[test]
+fn test_stale_reports_are_rejected_after_restart() {
+    let signer = ReportSigner::generate();
+    let window = std::time::Duration::from_millis(100);
+    let verifier = ReportVerifier::new(&[signer.public_key()])
+        .unwrap()
+        .with_nonce_window(window);
+
+    let signed = signer.sign(report(3.0));
+    assert!(verifier.verify(&signed).is_ok());
+
+    // A restarted server has forgotten the nonces it accepted
+    std::thread::sleep(window * 2);
+    let restarted = ReportVerifier::new(&[signer.public_key()])
+        .unwrap()
+        .with_nonce_window(window);
+    assert!(restarted.verify(&signed).is_err());
+    assert!(restarted.verify(&signer.sign(report(3.1))).is_ok());
+}
+
+// Test that published prices carry attestations consumers can verify offline
+#[test]
+fn test_published_prices_are_attested() {
+    let attestor = Arc::new(AttestationSigner::generate());
+    let public_key = attestor.public_key();
+    let store = PriceStore::new().with_attestor(attestor);
+
+    store.update(report(3.0), Utc::now()).unwrap();
+    let record = store.update(report(3.1), Utc::now()).unwrap();
+    let attestation = record.attestation.clone().unwrap();
+    assert_eq!(attestation.round_id, 2);
+
+    // Consumers receive the record as JSON, over WebSocket or REST
+    let received: PriceRecord =
+        serde_json::from_str(&serde_json::to_string(&record).unwrap()).unwrap();
+    assert!(verify_attestation(&received, &public_key).is_ok());
+
+    let mut tampered = received.clone();
+    tampered.price = 31.0;
+    assert!(verify_attestation(&tampered, &public_key).is_err());
+
+    let other_key = AttestationSigner::generate().public_key();
+    assert!(verify_attestation(&received, &other_key).is_err());
+}
+
+// Test that attestation rounds continue from the saved ones across restarts
+#[test]
+fn test_attestation_rounds_survive_restarts() {
+    let history = PriceHistory::open_in_memory(None).expect("Error opening history");
+    let attestor = Arc::new(AttestationSigner::generate());
+
+    let store = PriceStore::new().with_attestor(attestor.clone());
+    for price in [3.0, 3.1] {
+        history
+            .append(&store.update(report(price), Utc::now()).unwrap(), true)
+            .unwrap();
+    }
+
+    let restarted = PriceStore::new()
+        .with_attestor(attestor)
+        .with_last_rounds(history.last_rounds().unwrap());
+    let record = restarted.update(report(3.2), Utc::now()).unwrap();
+    assert_eq!(record.attestation.unwrap().round_id, 3);
+}
+
+// Test that a generated key is saved readable by its owner only, and loaded back
+#[cfg(unix)]
+#[test]
+fn test_generated_key_file_is_private() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = std::env::temp_dir().join(format!("client-{}.key", std::process::id()));
+    let path = path.to_str().unwrap();
+    std::fs::remove_file(path).ok();
+
+    let generated = ReportSigner::load_or_generate(path).unwrap();
+    let mode = std::fs::metadata(path).unwrap().permissions().mode();
+    let loaded = ReportSigner::load_or_generate(path).unwrap();
+    std::fs::remove_file(path).ok();
+
+    assert_eq!(mode & 0o777, 0o600);
+    assert_eq!(loaded.public_key(), generated.public_key());
+}
@@ -0,0 +1,122 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use rcgen::{BasicConstraints, Certificate, CertificateParams, IsCa, KeyPair};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use suicrypto_oracle::{
+    domain::{
+        price_provider::{PriceProvider, PriceQuote, TokenRef},
+        websocket_handler::WebSocketHandler,
+        websocket_server::WebSocketServer,
+    },
+    infraestructure::tls::{ClientTlsConfig, ServerTlsConfig},
+    AppError,
+};
+use tokio::sync::broadcast;
+
+#[derive(Debug)]
+struct FixedProvider;
+
+#[async_trait]
+impl PriceProvider for FixedProvider {
+    fn name(&self) -> &str {
+        "fixed"
+    }
+
+    async fn fetch(&self, token: &TokenRef) -> Result<PriceQuote, AppError> {
+        Ok(PriceQuote::new(token.name.clone(), 3.0, Utc::now()))
+    }
+}
+
+/// Writes a PEM file to the test directory and returns its path.
+fn write(dir: &Path, name: &str, pem: String) -> String {
+    let path = dir.join(name);
+    std::fs::write(&path, pem).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+/// Issues a certificate for `names`, signed by the given CA.
+fn issue(names: &[&str], ca: &Certificate, ca_key: &KeyPair) -> (String, String) {
+    let key = KeyPair::generate().unwrap();
+    let names: Vec<String> = names.iter().map(|n| n.to_string()).collect();
+    let cert = CertificateParams::new(names)
+        .unwrap()
+        .signed_by(&key, ca, ca_key)
+        .unwrap();
+    (cert.pem(), key.serialize_pem())
+}
+
+fn handler(address: &str) -> WebSocketHandler {
+    let (tx, _) = broadcast::channel(16);
+    WebSocketHandler::new(
+        TokenRef::new("SUI".to_string(), "0x2::sui::SUI".to_string()),
+        Arc::new(FixedProvider),
+        tx,
+    )
+    .with_server_host(address)
+}
+
+// Test that clients register over mutual TLS and that clients without a certificate are refused
+#[tokio::test]
+async fn test_mutual_tls_connection() {
+    let dir = std::env::temp_dir().join(format!("oracle-tls-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let ca_key = KeyPair::generate().unwrap();
+    let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let ca = ca_params.self_signed(&ca_key).unwrap();
+    let ca_path = write(&dir, "ca.pem", ca.pem());
+
+    let (server_cert, server_key) = issue(&["localhost"], &ca, &ca_key);
+    let (client_cert, client_key) = issue(&["sui-client"], &ca, &ca_key);
+    let server_cert = write(&dir, "server.pem", server_cert);
+    let server_key = write(&dir, "server.key", server_key);
+    let client_cert = write(&dir, "client.pem", client_cert);
+    let client_key = write(&dir, "client.key", client_key);
+
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let server_config = ServerTlsConfig::new(&server_cert, &server_key)
+        .with_client_ca(&ca_path)
+        .load()
+        .expect("Error loading server TLS config");
+    let server = WebSocketServer::new(&format!("127.0.0.1:{}", port))
+        .unwrap()
+        .with_tls(server_config);
+    let registry = server.registry();
+    let server_task = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // The certificate is issued for localhost, so connect by name
+    let address = format!("localhost:{}", port);
+
+    let anonymous = ClientTlsConfig::new().with_ca(&ca_path).load().unwrap();
+    let refused = handler(&address).with_tls(anonymous).connect().await;
+    assert!(refused.is_err());
+    assert!(registry.tokens().is_empty());
+
+    let authenticated = ClientTlsConfig::new()
+        .with_ca(&ca_path)
+        .with_client_cert(&client_cert, &client_key)
+        .load()
+        .unwrap();
+    let client = handler(&address).with_tls(authenticated);
+    let client_task = tokio::spawn(async move { client.connect().await });
+
+    let registered = tokio::time::timeout(Duration::from_secs(5), async {
+        while registry.clients("SUI").is_empty() {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await;
+    assert!(registered.is_ok());
+
+    client_task.abort();
+    server_task.abort();
+    std::fs::remove_dir_all(&dir).ok();
+}